@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use enigo::{Enigo, Key, KeyboardControllable};
+
+use crate::{
+    event_log::Direction,
+    hotkey::{self, HotKey},
+};
+
+/// Set once the run should be aborted (SIGINT, SIGTERM or the abort hotkey).
+static ABORT: AtomicBool = AtomicBool::new(false);
+
+/// Command line options for aborting a run.
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct AbortOptions {
+    /// Specifies a key (e.g. 'Pause') that aborts the run, like Ctrl-C
+    #[arg(long)]
+    pub abort_key: Option<HotKey>,
+}
+
+impl AbortOptions {
+    /// Abort the run on SIGINT / SIGTERM (and the abort hotkey, if given).
+    /// A second signal exits immediately.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the signal handler could not be installed.
+    pub fn install(self) -> Result<(), Box<dyn std::error::Error>> {
+        ctrlc::set_handler(|| {
+            if ABORT.swap(true, Ordering::Relaxed) {
+                println!("Aborted twice, exiting immediately");
+                std::process::exit(130);
+            }
+            println!("Aborting, press Ctrl-C again to exit immediately...");
+        })?;
+
+        if let Some(key) = self.abort_key {
+            println!("Press {key} to abort");
+            hotkey::register(key, request)?;
+        }
+        Ok(())
+    }
+}
+
+/// Request the run to be aborted.
+pub fn request() {
+    if !ABORT.swap(true, Ordering::Relaxed) {
+        println!("Aborting...");
+    }
+}
+
+/// If the run should be aborted.
+pub fn requested() -> bool {
+    ABORT.load(Ordering::Relaxed)
+}
+
+/// Keys that are currently held down by the simulation, so they can be released on abort.
+#[derive(Debug, Default)]
+pub struct HeldKeys(Vec<Key>);
+
+impl HeldKeys {
+    /// Track a key event that was just injected.
+    pub fn track(&mut self, key: Key, direction: Direction) {
+        match direction {
+            Direction::Down if !self.0.contains(&key) => self.0.push(key),
+            Direction::Down => {}
+            Direction::Up => self.0.retain(|held| *held != key),
+        }
+    }
+
+    /// Release every key that is still held down.
+    pub fn release_all(&mut self, keyboard: &mut Enigo) {
+        for key in self.0.drain(..).rev() {
+            println!("Releasing {key:?}");
+            keyboard.key_up(key);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use enigo::{Enigo, Key, KeyboardControllable};
+use rdev::{grab, Event, EventType};
+
+use crate::{
+    clock::Clock,
+    delay::{delay_sleep, DelayStrategy},
+    event_log::Direction,
+    realtime::RealtimeOptions,
+    run_info::RunInfo,
+    stats::Summary,
+};
+
+/// How long to wait for an injected event to be observed, before it is counted as missed.
+const OBSERVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Measured latency between calling enigo and the OS reporting the event.
+///
+/// Latencies are measured from the enigo call being made (the point events are scheduled at),
+/// so they include the time enigo itself takes (e.g. the per-key delay of its xdo backend).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CalibrationProfile {
+    /// Latency over all keys and directions, in seconds
+    pub overall: Summary,
+    /// Latency over all keys and directions measured from the enigo call returning, in seconds
+    /// (negative if the event is observed before enigo returns)
+    #[serde(default)]
+    pub returned: Option<Summary>,
+    /// Latency per key (`enigo::Key` debug representation, e.g. `Layout('a')`)
+    pub keys: BTreeMap<String, KeyLatency>,
+    /// How many injected events were never observed
+    pub missed: usize,
+}
+
+/// Latency of one key, split by direction.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct KeyLatency {
+    pub down: Option<Summary>,
+    pub up: Option<Summary>,
+}
+
+impl CalibrationProfile {
+    /// Read a calibration profile from a JSON file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if file could not be opened or is not a valid profile.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open(path.as_ref())?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write the calibration profile to a JSON file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if file could not be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = BufWriter::new(File::create(path.as_ref())?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+/// How a calibration profile is used to compensate for injection latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompensationMode {
+    /// Shift every event by the mean latency over all keys
+    Mean,
+    /// Shift every event by the mean latency measured for its key and direction
+    /// (keys missing from the profile use the overall mean)
+    PerKey,
+}
+
+/// Command line options for compensating injection latency with a calibration profile.
+#[derive(Debug, Clone, clap::Args)]
+pub struct CompensationOptions {
+    /// Specifies a calibration profile (written by 'calibrate') to shift all events earlier by the measured latency
+    #[arg(long)]
+    pub calibration: Option<String>,
+
+    /// Specifies if events are shifted by the mean latency or the latency measured per key
+    #[arg(long, value_enum, default_value_t = CompensationMode::Mean)]
+    pub compensation: CompensationMode,
+}
+
+impl CompensationOptions {
+    /// Load the calibration profile, if one is given.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the profile could not be read.
+    pub fn load(&self) -> Result<Option<Compensation>, Box<dyn std::error::Error>> {
+        let Some(path) = &self.calibration else {
+            return Ok(None);
+        };
+        let profile = CalibrationProfile::load(path)?;
+        let compensation = Compensation::new(path, self.compensation, &profile);
+        println!(
+            "Compensating {:?} latency of {path}: mean {:.1}µs, lead {:.1}µs",
+            self.compensation,
+            compensation.default * 1e6,
+            compensation.lead * 1e6
+        );
+        Ok(Some(compensation))
+    }
+}
+
+/// Offsets (in seconds) every event is shifted earlier by.
+/// Serialized as is into the run info, so the applied offsets are known afterwards.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Compensation {
+    /// Calibration profile the offsets are taken from
+    pub profile: String,
+    pub mode: CompensationMode,
+    /// Offset of every key not listed in `keys`
+    pub default: f64,
+    /// Offset per key (`enigo::Key` debug representation) and direction
+    pub keys: BTreeMap<String, KeyOffset>,
+    /// Largest offset, the schedule is started this much later so no event is scheduled before its start
+    pub lead: f64,
+}
+
+/// Offset of one key, split by direction.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct KeyOffset {
+    pub down: f64,
+    pub up: f64,
+}
+
+impl Compensation {
+    fn new(path: &str, mode: CompensationMode, profile: &CalibrationProfile) -> Self {
+        let keys = match mode {
+            CompensationMode::Mean => BTreeMap::new(),
+            CompensationMode::PerKey => profile
+                .keys
+                .iter()
+                .map(|(key, latency)| {
+                    let mean = |summary: &Option<Summary>| {
+                        summary.as_ref().map_or(profile.overall.mean, |s| s.mean)
+                    };
+                    let offset = KeyOffset {
+                        down: mean(&latency.down),
+                        up: mean(&latency.up),
+                    };
+                    (key.clone(), offset)
+                })
+                .collect(),
+        };
+
+        let lead = keys
+            .values()
+            .flat_map(|offset| [offset.down, offset.up])
+            .fold(profile.overall.mean, f64::max)
+            .max(0.0);
+
+        Self {
+            profile: path.to_owned(),
+            mode,
+            default: profile.overall.mean,
+            keys,
+            lead,
+        }
+    }
+
+    /// How many seconds an event of `key` in `direction` should be injected before its dataset time.
+    pub fn offset(&self, key: Key, direction: Direction) -> f64 {
+        self.keys
+            .get(&format!("{key:?}"))
+            .map_or(self.default, |offset| match direction {
+                Direction::Down => offset.down,
+                Direction::Up => offset.up,
+            })
+    }
+}
+
+/// Key rdev reports for an injected key, `None` if it can not be told apart (on a US layout).
+fn observed_key(key: Key) -> Option<rdev::Key> {
+    use rdev::Key as K;
+
+    const LETTERS: [K; 26] = [
+        K::KeyA,
+        K::KeyB,
+        K::KeyC,
+        K::KeyD,
+        K::KeyE,
+        K::KeyF,
+        K::KeyG,
+        K::KeyH,
+        K::KeyI,
+        K::KeyJ,
+        K::KeyK,
+        K::KeyL,
+        K::KeyM,
+        K::KeyN,
+        K::KeyO,
+        K::KeyP,
+        K::KeyQ,
+        K::KeyR,
+        K::KeyS,
+        K::KeyT,
+        K::KeyU,
+        K::KeyV,
+        K::KeyW,
+        K::KeyX,
+        K::KeyY,
+        K::KeyZ,
+    ];
+    const DIGITS: [K; 10] = [
+        K::Num0,
+        K::Num1,
+        K::Num2,
+        K::Num3,
+        K::Num4,
+        K::Num5,
+        K::Num6,
+        K::Num7,
+        K::Num8,
+        K::Num9,
+    ];
+
+    match key {
+        Key::Layout(c @ 'a'..='z') => Some(LETTERS[usize::from(c as u8 - b'a')]),
+        Key::Layout(c @ '0'..='9') => Some(DIGITS[usize::from(c as u8 - b'0')]),
+        Key::Layout(' ') | Key::Space => Some(K::Space),
+        Key::Return => Some(K::Return),
+        Key::Tab => Some(K::Tab),
+        _ => None,
+    }
+}
+
+/// Inject `count` keys and measure how long it takes for the OS to report each event.
+///
+/// Latency is measured from the enigo call being made, the same point scheduled events are
+/// fired at. The latency from the call returning is recorded alongside it.
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+pub fn calibrate<R: AsRef<Path>>(
+    output: R,
+    count: usize,
+    keys: &str,
+    hold: f64,
+    delay: f64,
+    baseline: Option<&str>,
+    strategy: DelayStrategy,
+    clock: &Arc<dyn Clock>,
+    realtime: RealtimeOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("[Injection latency calibration]");
+
+    let keys = keys
+        .chars()
+        .map(|c| {
+            let key = Key::Layout(c);
+            observed_key(key)
+                .map(|observed| (key, observed))
+                .ok_or_else(|| {
+                    crate::Error(format!(
+                        "Can not calibrate key '{c}', use lowercase letters, digits or space"
+                    ))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err(Box::new(crate::Error("No keys to calibrate given".into())));
+    }
+
+    let baseline = baseline.map(CalibrationProfile::load).transpose()?;
+
+    // pin thread and raise its priority, record what is really in effect
+    let mut run_info = RunInfo::for_output(output.as_ref());
+    run_info.set("realtime", realtime.apply())?;
+    run_info.save()?;
+
+    // forward every observed key event (with the key and the time it was observed) to the injecting thread
+    let (sender, receiver) = mpsc::channel();
+    let callback_clock = clock.clone();
+    let callback = move |event: Event| -> Option<Event> {
+        let observed = callback_clock.now();
+        match event.event_type {
+            EventType::KeyPress(key) => _ = sender.send((key, Direction::Down, observed)),
+            EventType::KeyRelease(key) => _ = sender.send((key, Direction::Up, observed)),
+            _ => {}
+        }
+        Some(event)
+    };
+
+    // spawn thread to process key-events
+    thread::spawn(move || {
+        // This will block.
+        if let Err(error) = grab(callback) {
+            println!("Error: {error:?}");
+        }
+    });
+
+    let mut enigo = Enigo::new();
+
+    println!("Focus an empty text field! Injecting {count} keys after 5 sec...");
+    delay_sleep(&**clock, 5.0);
+    println!("Starting... (latency is measured from the enigo call being made)");
+
+    let mut latencies = BTreeMap::<String, (Vec<f64>, Vec<f64>)>::new();
+    let mut returned = Vec::new();
+    let mut missed = 0;
+
+    for i in 0..count {
+        let (key, expected) = keys[i % keys.len()];
+        let entry = latencies.entry(format!("{key:?}")).or_default();
+
+        for direction in [Direction::Down, Direction::Up] {
+            // drop events that were not caused by us
+            while receiver.try_recv().is_ok() {}
+
+            let injected = clock.now();
+            match direction {
+                Direction::Down => enigo.key_down(key),
+                Direction::Up => enigo.key_up(key),
+            }
+            let call_returned = clock.now();
+
+            // wait for the matching event to be observed
+            let observed = loop {
+                match receiver.recv_timeout(OBSERVE_TIMEOUT) {
+                    Ok((k, d, observed)) if k == expected && d == direction => {
+                        break Some(observed)
+                    }
+                    Ok(_) => {}
+                    Err(_) => break None,
+                }
+            };
+
+            match observed {
+                Some(observed) => {
+                    let latency = observed.as_secs_f64() - injected.as_secs_f64();
+                    match direction {
+                        Direction::Down => entry.0.push(latency),
+                        Direction::Up => entry.1.push(latency),
+                    }
+                    returned.push(observed.as_secs_f64() - call_returned.as_secs_f64());
+                }
+                None => missed += 1,
+            }
+
+            if direction == Direction::Down {
+                strategy.delay(&**clock, hold);
+            }
+        }
+
+        strategy.delay(&**clock, delay);
+    }
+
+    let all = latencies
+        .values()
+        .flat_map(|(down, up)| down.iter().chain(up))
+        .copied()
+        .collect::<Vec<_>>();
+    let Some(overall) = Summary::new(&all) else {
+        return Err(Box::new(crate::Error(
+            "No injected event was observed".into(),
+        )));
+    };
+
+    let profile = CalibrationProfile {
+        overall,
+        returned: Summary::new(&returned),
+        keys: latencies
+            .into_iter()
+            .map(|(key, (down, up))| {
+                let latency = KeyLatency {
+                    down: Summary::new(&down),
+                    up: Summary::new(&up),
+                };
+                (key, latency)
+            })
+            .collect(),
+        missed,
+    };
+
+    println!(
+        "Latency (from the enigo call being made): {}",
+        profile.overall
+    );
+    if let Some(returned) = &profile.returned {
+        println!("Latency (from the enigo call returning): {returned}");
+    }
+    if missed > 0 {
+        println!("Warning: {missed} injected events were never observed");
+    }
+    if let Some(baseline) = baseline {
+        println!(
+            "Mean latency changed by {:+.1}µs compared to baseline ({:.1}µs -> {:.1}µs)",
+            (profile.overall.mean - baseline.overall.mean) * 1e6,
+            baseline.overall.mean * 1e6,
+            profile.overall.mean * 1e6
+        );
+    }
+
+    profile.save(output.as_ref())?;
+    println!(
+        "Calibration profile written to {}",
+        output.as_ref().display()
+    );
+
+    Ok(())
+}
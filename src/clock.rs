@@ -0,0 +1,159 @@
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+use std::sync::Mutex;
+
+/// Source of time for all delays, schedulers and progress reports.
+pub trait Clock: Debug + Send + Sync {
+    /// Time elapsed since an arbitrary, but fixed origin.
+    fn now(&self) -> Duration;
+
+    /// Time elapsed since clock time `start`.
+    fn elapsed_since(&self, start: Duration) -> Duration {
+        self.now().saturating_sub(start)
+    }
+
+    /// Pause the current thread for (at least) `dur`.
+    fn sleep(&self, dur: Duration);
+
+    /// Called on every iteration of a busy-loop waiting on this clock.
+    fn spin(&self) {
+        std::hint::spin_loop();
+    }
+}
+
+/// Command line option selecting the clock all delays are measured with.
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct ClockOptions {
+    /// Specifies which clock all delays are measured with
+    #[arg(long, value_enum, default_value_t = ClockKind::Std)]
+    pub clock: ClockKind,
+}
+
+impl ClockOptions {
+    /// Create the selected clock, it is created once and shared by the whole run.
+    pub fn create(self) -> Arc<dyn Clock> {
+        self.clock.create()
+    }
+}
+
+/// Which clock implementation to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClockKind {
+    /// `std::time::Instant`
+    Std,
+    /// `CLOCK_MONOTONIC_RAW` (not slewed by NTP, Linux only)
+    MonotonicRaw,
+}
+
+impl ClockKind {
+    /// Create a new clock of this kind.
+    /// Falls back to `Std`, if the clock is not supported on this platform.
+    pub fn create(self) -> Arc<dyn Clock> {
+        match self {
+            Self::Std => Arc::new(StdClock::new()),
+            #[cfg(target_os = "linux")]
+            Self::MonotonicRaw => Arc::new(MonotonicRawClock),
+            #[cfg(not(target_os = "linux"))]
+            Self::MonotonicRaw => {
+                println!(
+                    "Warning: CLOCK_MONOTONIC_RAW is only supported on linux, using std clock"
+                );
+                Arc::new(StdClock::new())
+            }
+        }
+    }
+}
+
+/// Clock based on `std::time::Instant`.
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    origin: Instant,
+}
+
+impl StdClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for StdClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&self, dur: Duration) {
+        std::thread::sleep(dur);
+    }
+}
+
+/// Clock based on `CLOCK_MONOTONIC_RAW`.
+/// Unlike `Instant` (`CLOCK_MONOTONIC`), its rate is not adjusted by NTP.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicRawClock;
+
+#[cfg(target_os = "linux")]
+impl Clock for MonotonicRawClock {
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    fn now(&self) -> Duration {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `ts` is a valid, writable timespec and CLOCK_MONOTONIC_RAW is supported since Linux 2.6.28
+        let res =
+            unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC_RAW, std::ptr::addr_of_mut!(ts)) };
+        assert_eq!(res, 0, "clock_gettime(CLOCK_MONOTONIC_RAW) failed");
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    fn sleep(&self, dur: Duration) {
+        std::thread::sleep(dur);
+    }
+}
+
+/// Manually advanced clock for tests.
+/// Sleeping advances the clock by the slept duration, every busy-loop iteration by `spin_step`.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<Duration>,
+    spin_step: Duration,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(spin_step: Duration) -> Self {
+        Self {
+            now: Mutex::new(Duration::ZERO),
+            spin_step,
+        }
+    }
+
+    /// Move the clock forward by `dur`.
+    pub fn advance(&self, dur: Duration) {
+        *self.now.lock().unwrap() += dur;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, dur: Duration) {
+        self.advance(dur);
+    }
+
+    fn spin(&self) {
+        self.advance(self.spin_step);
+    }
+}
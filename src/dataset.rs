@@ -0,0 +1,624 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use enigo::Key;
+
+use crate::{
+    event_log::Direction,
+    free_text_data::{self, KeyEncoding, Keystroke, ParseError, Sample},
+    pw_timer, Error,
+};
+
+/// One key event of a keystroke dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DatasetEvent {
+    /// Timestamp in ms, relative to an arbitrary start
+    pub timestamp: f64,
+    /// JavaScript key code, except for the free-text line pairs, which also contain character codes
+    /// (see [`KeyEncoding::detect`])
+    pub code: u8,
+    pub direction: Direction,
+    /// Line of the dataset file the event was read from, if known
+    pub line: Option<usize>,
+}
+
+/// Key events of one recording (a typed password or text), ordered by time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// Name of the recording, e.g. the file it was read from
+    pub name: String,
+    /// Where the recording comes from, e.g. `subject`, `session` and `rep`
+    pub metadata: BTreeMap<String, String>,
+    pub events: Vec<DatasetEvent>,
+}
+
+impl Recording {
+    /// Recording without metadata, its events are sorted by time.
+    pub fn new(name: String, mut events: Vec<DatasetEvent>) -> Self {
+        // stable, so a keydown stays before a keyup at the same time
+        events.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        Self {
+            name,
+            metadata: BTreeMap::new(),
+            events,
+        }
+    }
+
+    /// Free-text sample of the keydowns, held down until their recorded keyup.
+    /// Keys without a keyup are left to the hold-time model.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn into_sample(self) -> Sample {
+        let time = |event: &DatasetEvent| event.timestamp.max(0.0).round() as u64;
+        let keystrokes = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| event.direction == Direction::Down)
+            .map(|(i, event)| {
+                // the next event of the key, unless it is pressed again
+                let hold = self.events[i + 1..]
+                    .iter()
+                    .find(|next| next.code == event.code)
+                    .filter(|next| next.direction == Direction::Up)
+                    .map(|up| time(up).saturating_sub(time(event)));
+                Keystroke {
+                    timestamp: time(event),
+                    code: event.code,
+                    hold,
+                    line: event.line,
+                }
+            })
+            .collect();
+        Sample {
+            name: self.name,
+            keystrokes,
+        }
+    }
+}
+
+/// Reads a keystroke dataset as recordings of key events.
+pub trait DatasetReader {
+    /// Read every recording of the dataset at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the dataset could not be read or parsed.
+    fn read(&self, path: &Path) -> Result<Vec<Recording>, Box<dyn std::error::Error>>;
+}
+
+/// Format of a keystroke dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatasetFormat {
+    /// Detect the format from the file extension (and the first line of '.txt' files)
+    Auto,
+    /// CMU password dataset ('DSL-StrongPasswordData.csv'), with 'H.*', 'DD.*' and 'UD.*' columns
+    Cmu,
+    /// Alternating lines of timestamp and key code ('KEYSTROKE-SAMPLES-31-USERS'),
+    /// either a single file or a JSON list of files
+    LinePairs,
+    /// Tab separated press/release times with a key name or code per row (e.g. the Aalto 136M keystrokes)
+    Tsv,
+    /// JSON lines, one key event per line
+    Jsonl,
+}
+
+impl DatasetFormat {
+    /// Format of the dataset at `path`, `auto` is detected from the file extension.
+    /// A '.txt' file is tab separated if its first line contains a tab (e.g. '<id>_keystrokes.txt'),
+    /// line pairs otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the format can not be detected.
+    pub fn resolve(self, path: &Path) -> Result<Self, Error> {
+        if self != Self::Auto {
+            return Ok(self);
+        }
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("csv") => Ok(Self::Cmu),
+            Some("txt") if first_line(path).contains('\t') => Ok(Self::Tsv),
+            Some("json" | "txt") => Ok(Self::LinePairs),
+            Some("tsv" | "tab") => Ok(Self::Tsv),
+            Some("jsonl") => Ok(Self::Jsonl),
+            _ => Err(Error(format!(
+                "Can not detect the dataset format of {}, use --format",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Reader for the dataset at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the format can not be detected.
+    pub fn reader(self, path: &Path) -> Result<Box<dyn DatasetReader>, Error> {
+        let reader: Box<dyn DatasetReader> = match self.resolve(path)? {
+            Self::Auto => unreachable!("resolved above"),
+            Self::Cmu => Box::new(CmuReader),
+            Self::LinePairs => Box::new(LinePairReader),
+            Self::Tsv => Box::new(TsvReader),
+            Self::Jsonl => Box::new(JsonlReader),
+        };
+        Ok(reader)
+    }
+
+    /// Read the dataset at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the format can not be detected or the dataset could not be read.
+    pub fn read<P: AsRef<Path>>(
+        self,
+        path: P,
+    ) -> Result<Vec<Recording>, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let recordings = self.reader(path)?.read(path)?;
+        println!(
+            "Read {} recordings ({:?})",
+            recordings.len(),
+            self.resolve(path)?
+        );
+        Ok(recordings)
+    }
+}
+
+/// CMU password dataset, every row is a recording of the password.
+#[derive(Debug)]
+pub struct CmuReader;
+
+impl DatasetReader for CmuReader {
+    fn read(&self, path: &Path) -> Result<Vec<Recording>, Box<dyn std::error::Error>> {
+        pw_timer::read_recordings(path)
+    }
+}
+
+/// Free-text dataset, every file is a recording of keydowns.
+#[derive(Debug)]
+pub struct LinePairReader;
+
+impl DatasetReader for LinePairReader {
+    #[allow(clippy::cast_precision_loss)]
+    fn read(&self, path: &Path) -> Result<Vec<Recording>, Box<dyn std::error::Error>> {
+        let samples = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            free_text_data::read_dataset(path)?
+        } else {
+            vec![free_text_data::read_sample(path)?]
+        };
+
+        let recordings = samples
+            .into_iter()
+            .map(|sample| {
+                let events = sample
+                    .keystrokes
+                    .iter()
+                    .map(|keystroke| DatasetEvent {
+                        timestamp: keystroke.timestamp as f64,
+                        code: keystroke.code,
+                        direction: Direction::Down,
+                        line: keystroke.line,
+                    })
+                    .collect();
+                Recording::new(sample.name, events)
+            })
+            .collect();
+        Ok(recordings)
+    }
+}
+
+/// Tab separated press/release log with a header, one row per key press.
+///
+/// Press and release times (in ms) are read from the `PRESS_TIME` and `RELEASE_TIME` columns,
+/// the key from `KEYCODE` or `LETTER` (a key name, e.g. 'a', 'SHIFT' or 'BKSP').
+/// Rows are grouped into recordings by `PARTICIPANT_ID` and `TEST_SECTION_ID`, if given.
+#[derive(Debug)]
+pub struct TsvReader;
+
+impl DatasetReader for TsvReader {
+    fn read(&self, path: &Path) -> Result<Vec<Recording>, Box<dyn std::error::Error>> {
+        Ok(parse_tsv(&file_name(path), &read_file(path)?)?)
+    }
+}
+
+/// JSON lines, one key event per line, e.g.
+/// `{"sample": "s01", "timestamp": 1042.5, "key": "Shift", "direction": "down"}`.
+///
+/// The key is a key name (a string) or key code (a number), the direction `down` / `up` (or `keydown` / `keyup`).
+/// Events are grouped into recordings by `sample`, all other fields are kept as metadata.
+#[derive(Debug)]
+pub struct JsonlReader;
+
+impl DatasetReader for JsonlReader {
+    fn read(&self, path: &Path) -> Result<Vec<Recording>, Box<dyn std::error::Error>> {
+        Ok(parse_jsonl(&file_name(path), &read_file(path)?)?)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ParseError> {
+    std::fs::read_to_string(path).map_err(|source| ParseError::Io {
+        file: PathBuf::from(path),
+        source,
+    })
+}
+
+/// First line of the file at `path`, empty if it can not be read.
+fn first_line(path: &Path) -> String {
+    File::open(path)
+        .ok()
+        .and_then(|file| BufReader::new(file).lines().next()?.ok())
+        .unwrap_or_default()
+}
+
+/// File name of `path`, without its directories.
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// Key named in a dataset (case insensitive), e.g. 'a', 'Shift', 'BKSP' or 'Enter'.
+fn key_from_name(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Layout(c));
+    }
+
+    let key = match name.to_ascii_lowercase().as_str() {
+        "shift" | "lshift" | "rshift" | "shift_l" | "shift_r" | "shiftleft" | "shiftright" => {
+            Key::Shift
+        }
+        "space" => Key::Space,
+        "backspace" | "bksp" => Key::Backspace,
+        "tab" => Key::Tab,
+        "enter" | "return" => Key::Return,
+        "delete" | "del" => Key::Delete,
+        "left" | "arrowleft" => Key::LeftArrow,
+        "right" | "arrowright" => Key::RightArrow,
+        "up" | "arrowup" => Key::UpArrow,
+        "down" | "arrowdown" => Key::DownArrow,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" | "pgup" => Key::PageUp,
+        "pagedown" | "pgdn" => Key::PageDown,
+        "control" | "ctrl" => Key::Control,
+        "alt" => Key::Alt,
+        "capslock" | "caps_lock" => Key::CapsLock,
+        "escape" | "esc" => Key::Escape,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Key code of a key name, digits are names of the digit keys.
+fn key_code(name: &str) -> Option<u8> {
+    key_from_name(name).and_then(KeyEncoding::key_code)
+}
+
+/// Parse a tab separated press/release log, see [`TsvReader`].
+///
+/// # Errors
+///
+/// Returns an error, if a required column is missing or a row can not be parsed.
+pub fn parse_tsv(name: &str, content: &str) -> Result<Vec<Recording>, ParseError> {
+    let error = |line: usize, reason: String| ParseError::Record {
+        file: name.to_owned(),
+        line,
+        reason,
+    };
+
+    let mut lines = content.lines().enumerate();
+    let header = lines
+        .next()
+        .map(|(_, header)| header.split('\t').collect::<Vec<_>>())
+        .unwrap_or_default();
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|h| names.iter().any(|name| h.trim().eq_ignore_ascii_case(name)))
+    };
+
+    let missing = |names: &str| error(1, format!("Missing column {names}"));
+    let press = column(&["PRESS_TIME", "press"]).ok_or_else(|| missing("PRESS_TIME"))?;
+    let release = column(&["RELEASE_TIME", "release"]).ok_or_else(|| missing("RELEASE_TIME"))?;
+    // key codes are only read from a key code column, other columns name the key
+    let (key, numeric) = match column(&["KEYCODE"]) {
+        Some(key) => (key, true),
+        None => (
+            column(&["LETTER", "key"]).ok_or_else(|| missing("KEYCODE or LETTER"))?,
+            false,
+        ),
+    };
+    let subject = column(&["PARTICIPANT_ID", "subject"]);
+    let session = column(&["TEST_SECTION_ID", "session"]);
+
+    // recordings in order of their first row
+    let mut recordings: Vec<Recording> = Vec::new();
+    for (n, row) in lines {
+        // line numbers start at 1
+        let line = n + 1;
+        if row.trim().is_empty() {
+            continue;
+        }
+        // keep the fields untrimmed, ' ' is the name of the space key
+        let fields = row.split('\t').collect::<Vec<_>>();
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+
+        let time = |i: usize| {
+            field(i)
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| error(line, format!("Invalid time '{}'", field(i))))
+        };
+        let (pressed, released) = (time(press)?, time(release)?);
+        let code = if numeric {
+            field(key).trim().parse::<u8>().ok()
+        } else {
+            key_code(field(key))
+        }
+        .ok_or_else(|| error(line, format!("Unknown key '{}'", field(key))))?;
+
+        let metadata = [("subject", subject), ("session", session)]
+            .into_iter()
+            .filter_map(|(name, i)| Some((name.to_owned(), field(i?).trim().to_owned())))
+            .collect::<Vec<_>>();
+        let recording_name = if metadata.is_empty() {
+            name.to_owned()
+        } else {
+            let values = metadata.iter().map(|(_, value)| value.as_str());
+            values.collect::<Vec<_>>().join("-")
+        };
+
+        let position = recordings
+            .iter()
+            .position(|r| r.name == recording_name)
+            .unwrap_or_else(|| {
+                let mut recording = Recording::new(recording_name, Vec::new());
+                recording.metadata = metadata.into_iter().collect();
+                recordings.push(recording);
+                recordings.len() - 1
+            });
+        recordings[position].events.extend([
+            DatasetEvent {
+                timestamp: pressed,
+                code,
+                direction: Direction::Down,
+                line: Some(line),
+            },
+            DatasetEvent {
+                timestamp: released,
+                code,
+                direction: Direction::Up,
+                line: Some(line),
+            },
+        ]);
+    }
+
+    Ok(recordings
+        .into_iter()
+        .map(|recording| Recording {
+            metadata: recording.metadata,
+            ..Recording::new(recording.name, recording.events)
+        })
+        .collect())
+}
+
+/// Parse JSON lines of key events, see [`JsonlReader`].
+///
+/// # Errors
+///
+/// Returns an error, if a line is not a JSON object describing a key event.
+pub fn parse_jsonl(name: &str, content: &str) -> Result<Vec<Recording>, ParseError> {
+    let mut recordings: Vec<Recording> = Vec::new();
+
+    for (n, line) in content.lines().enumerate() {
+        let line_number = n + 1;
+        let error = |reason: String| ParseError::Record {
+            file: name.to_owned(),
+            line: line_number,
+            reason,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let serde_json::Value::Object(mut fields) =
+            serde_json::from_str(line).map_err(|e| error(e.to_string()))?
+        else {
+            return Err(error("Expected a JSON object".to_owned()));
+        };
+        let mut take = |field: &str| {
+            fields
+                .remove(field)
+                .ok_or_else(|| error(format!("Missing field '{field}'")))
+        };
+
+        let timestamp = take("timestamp")?
+            .as_f64()
+            .ok_or_else(|| error("Invalid timestamp".to_owned()))?;
+        let code = match take("key")? {
+            serde_json::Value::String(key) => key_code(&key),
+            key => key.as_u64().and_then(|code| u8::try_from(code).ok()),
+        }
+        .ok_or_else(|| error("Unknown key".to_owned()))?;
+        let direction = match take("direction")?.as_str() {
+            Some("down" | "keydown") => Direction::Down,
+            Some("up" | "keyup") => Direction::Up,
+            _ => {
+                return Err(error(
+                    "Invalid direction, expected 'down' or 'up'".to_owned(),
+                ))
+            }
+        };
+        let sample = match fields.remove("sample") {
+            Some(serde_json::Value::String(sample)) => sample,
+            Some(sample) => sample.to_string(),
+            None => name.to_owned(),
+        };
+
+        let position = recordings
+            .iter()
+            .position(|r| r.name == sample)
+            .unwrap_or_else(|| {
+                let mut recording = Recording::new(sample, Vec::new());
+                // the remaining fields of the first event describe the recording
+                recording.metadata = fields
+                    .into_iter()
+                    .map(|(field, value)| match value {
+                        serde_json::Value::String(value) => (field, value),
+                        value => (field, value.to_string()),
+                    })
+                    .collect();
+                recordings.push(recording);
+                recordings.len() - 1
+            });
+        recordings[position].events.push(DatasetEvent {
+            timestamp,
+            code,
+            direction,
+            line: Some(line_number),
+        });
+    }
+
+    Ok(recordings
+        .into_iter()
+        .map(|recording| Recording {
+            metadata: recording.metadata,
+            ..Recording::new(recording.name, recording.events)
+        })
+        .collect())
+}
+
+#[test]
+fn test_dataset_adapters() {
+    let down = |timestamp, code, line| DatasetEvent {
+        timestamp,
+        code,
+        direction: Direction::Down,
+        line: Some(line),
+    };
+    let up = |timestamp, code, line| DatasetEvent {
+        timestamp,
+        code,
+        direction: Direction::Up,
+        line: Some(line),
+    };
+
+    let tsv = "PARTICIPANT_ID\tTEST_SECTION_ID\tPRESS_TIME\tRELEASE_TIME\tLETTER\n\
+               5\t1\t100\t200\tSHIFT\n\
+               5\t1\t150\t180\tA\n\
+               5\t1\t300\t350\t \n\
+               5\t2\t10\t20\tBKSP\n\
+               5\t2\t30\t40\t1\n";
+    let recordings = parse_tsv("5_keystrokes.txt", tsv).unwrap();
+    assert_eq!(recordings.len(), 2);
+    assert_eq!(recordings[0].name, "5-1");
+    assert_eq!(recordings[0].metadata["subject"], "5");
+    assert_eq!(
+        recordings[0].events,
+        [
+            down(100.0, 16, 2),
+            down(150.0, 65, 3),
+            up(180.0, 65, 3),
+            up(200.0, 16, 2),
+            down(300.0, 32, 4),
+            up(350.0, 32, 4),
+        ]
+    );
+    // '1' is the name of the digit key, not key code 1
+    let sample = recordings[1].clone().into_sample();
+    assert_eq!(
+        sample.keystrokes,
+        [
+            Keystroke {
+                timestamp: 10,
+                code: 8,
+                hold: Some(10),
+                line: Some(5)
+            },
+            Keystroke {
+                timestamp: 30,
+                code: 49,
+                hold: Some(10),
+                line: Some(6)
+            }
+        ]
+    );
+    // Shift is held down until its keyup, around 'A'
+    let sample = recordings[0].clone().into_sample();
+    let holds = sample.keystrokes.iter().map(|k| k.hold).collect::<Vec<_>>();
+    assert_eq!(holds, [Some(100), Some(30), Some(50)]);
+    let list = free_text_data::create_task_list(
+        &sample.keystrokes,
+        free_text_data::ShiftMode::Faithful,
+        &mut crate::hold::HoldModel::none(),
+    );
+    assert_eq!(list.holds, [Some(0.1), Some(0.03), Some(0.05)]);
+    let codes = parse_tsv("x.tsv", "PRESS_TIME\tRELEASE_TIME\tKEYCODE\n1\t2\t1\n").unwrap();
+    assert_eq!(codes[0].events[0].code, 1);
+
+    let error = parse_tsv("x.tsv", "PRESS_TIME\tRELEASE_TIME\tLETTER\n1\t2\tFOO\n").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid record in x.tsv (line 2): Unknown key 'FOO'"
+    );
+
+    let jsonl = r#"{"sample": "s01", "timestamp": 5, "key": "Shift", "direction": "down", "subject": "u1"}
+{"sample": "s01", "timestamp": 0, "key": 72, "direction": "keyup"}
+
+{"sample": 2, "timestamp": 1.5, "key": "?", "direction": "down"}
+"#;
+    let recordings = parse_jsonl("events.jsonl", jsonl).unwrap();
+    assert_eq!(recordings.len(), 2);
+    assert_eq!(recordings[0].metadata["subject"], "u1");
+    assert_eq!(recordings[0].events, [up(0.0, 72, 2), down(5.0, 16, 1)]);
+    assert_eq!(recordings[1].name, "2");
+    assert_eq!(recordings[1].events, [down(1.5, 191, 4)]);
+    // only the keydown is recorded, the hold time is synthesized
+    assert_eq!(recordings[1].clone().into_sample().keystrokes[0].hold, None);
+
+    let error = parse_jsonl("events.jsonl", r#"{"timestamp": 1, "key": "a"}"#).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid record in events.jsonl (line 1): Missing field 'direction'"
+    );
+}
+
+#[test]
+fn test_format_detection() {
+    assert_eq!(
+        DatasetFormat::Auto
+            .resolve(Path::new("split_1-7.json"))
+            .unwrap(),
+        DatasetFormat::LinePairs
+    );
+    assert!(DatasetFormat::Auto.resolve(Path::new("data")).is_err());
+
+    // '.txt' files are either line pairs or tab separated
+    let dir = std::env::temp_dir().join(format!("browser-timer-rs-dataset-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (pairs, aalto) = (dir.join("s01.txt"), dir.join("5_keystrokes.txt"));
+    std::fs::write(&pairs, "10\n72\n").unwrap();
+    std::fs::write(&aalto, "PRESS_TIME\tRELEASE_TIME\tLETTER\n1\t2\ta\n").unwrap();
+    assert_eq!(
+        DatasetFormat::Auto.resolve(&pairs).unwrap(),
+        DatasetFormat::LinePairs
+    );
+    assert_eq!(
+        DatasetFormat::Auto.resolve(&aalto).unwrap(),
+        DatasetFormat::Tsv
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,4 +1,69 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+use crate::{abort, clock::Clock};
+
+/// Default time (in seconds) before a deadline at which `delay_hybrid` stops sleeping and starts spinning.
+pub const DEFAULT_SPIN_MARGIN: f64 = 0.002;
+
+/// Longest time slept at once, so an abort of the run ends a sleep early.
+const ABORT_POLL: Duration = Duration::from_millis(50);
+
+/// How waits between simulated input events are performed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DelayMode {
+    /// Busy-loop for the whole wait (most precise, keeps one core at 100%)
+    Busy,
+    /// Sleep for the whole wait (cheap, but imprecise)
+    Sleep,
+    /// Sleep until shortly before the deadline, then busy-loop for the rest
+    Hybrid,
+}
+
+/// Command line options selecting the delay primitive used by a subcommand.
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct DelayStrategy {
+    /// Specifies how to wait between input events
+    #[arg(long, value_enum, default_value_t = DelayMode::Busy)]
+    pub delay_mode: DelayMode,
+
+    /// Specifies how many seconds before a deadline the 'hybrid' delay mode switches from sleeping to spinning
+    #[arg(long, default_value_t = DEFAULT_SPIN_MARGIN, value_parser = parse_spin_margin)]
+    pub spin_margin: f64,
+}
+
+/// Parse a spin margin, which has to be a finite, non-negative number of seconds.
+fn parse_spin_margin(s: &str) -> Result<f64, String> {
+    let margin = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if margin.is_finite() && margin >= 0.0 {
+        Ok(margin)
+    } else {
+        Err(format!(
+            "{margin} is not a finite, non-negative number of seconds"
+        ))
+    }
+}
+
+impl DelayStrategy {
+    /// Take number of seconds to delay/pause execution, using the selected delay mode.
+    /// Returns the real duration that was delayed.
+    pub fn delay(&self, clock: &dyn Clock, dur: f64) -> Duration {
+        match self.delay_mode {
+            DelayMode::Busy => delay_busy(clock, dur),
+            DelayMode::Sleep => delay_sleep(clock, dur),
+            DelayMode::Hybrid => delay_hybrid(clock, dur, self.spin_margin),
+        }
+    }
+
+    /// Delay/pause execution until `clock` reaches `deadline`, using the selected delay mode.
+    /// Returns the clock time the wait actually ended at.
+    pub fn delay_until(&self, clock: &dyn Clock, deadline: Duration) -> Duration {
+        match self.delay_mode {
+            DelayMode::Busy => delay_until_busy(clock, deadline),
+            DelayMode::Sleep => delay_until_sleep(clock, deadline),
+            DelayMode::Hybrid => delay_until_hybrid(clock, deadline, self.spin_margin),
+        }
+    }
+}
 
 #[inline]
 /// Take number of seconds to delay/pause execution.
@@ -7,47 +72,141 @@ use std::time::{Duration, Instant};
 ///
 /// # Panics
 /// Panics if passed duration is negativ.
-pub fn delay_busy(dur: f64) -> Duration {
+pub fn delay_busy(clock: &dyn Clock, dur: f64) -> Duration {
     assert!(dur >= 0.0, "{dur}");
     if dur == 0.0 {
         return Duration::from_secs(0);
     }
-    let duration = Duration::from_secs_f64(dur);
-    let start = Instant::now();
-    loop {
-        let elapsed = start.elapsed();
-        if elapsed >= duration {
-            return elapsed;
-        }
-    }
+    let start = clock.now();
+    delay_until_busy(clock, start + Duration::from_secs_f64(dur)).saturating_sub(start)
 }
 
 #[inline]
 /// Take number of seconds to delay/pause execution.
 /// Returns the real duration that was delayed.
-/// This function uses `std::thread::sleep`.
+/// This function uses `Clock::sleep`, it returns early if the run is aborted.
 ///
 /// # Panics
 /// Panics if passed duration is negativ.
-pub fn delay_sleep(dur: f64) -> Duration {
+pub fn delay_sleep(clock: &dyn Clock, dur: f64) -> Duration {
+    assert!(dur >= 0.0, "{dur}");
+    if dur == 0.0 {
+        return Duration::from_secs(0);
+    }
+    let start = clock.now();
+    sleep_until(clock, start + Duration::from_secs_f64(dur));
+    clock.elapsed_since(start)
+}
+
+#[inline]
+/// Take number of seconds to delay/pause execution.
+/// Returns the real duration that was delayed.
+/// This function uses `Clock::sleep` until `margin` seconds before the deadline
+/// and a `busy-loop` for the remaining time.
+///
+/// If the sleep itself overshoots the margin, the returned duration will be longer than requested.
+///
+/// # Panics
+/// Panics if passed duration or margin is negativ.
+pub fn delay_hybrid(clock: &dyn Clock, dur: f64, margin: f64) -> Duration {
     assert!(dur >= 0.0, "{dur}");
     if dur == 0.0 {
         return Duration::from_secs(0);
     }
-    let now = Instant::now();
-    std::thread::sleep(Duration::from_secs_f64(dur));
-    now.elapsed()
+    let start = clock.now();
+    delay_until_hybrid(clock, start + Duration::from_secs_f64(dur), margin).saturating_sub(start)
+}
+
+#[inline]
+/// Delay/pause execution until `clock` reaches `deadline`.
+/// Returns the clock time the wait actually ended at.
+/// This function uses a `busy-loop` while checking the current time, it returns early if the run is aborted.
+pub fn delay_until_busy(clock: &dyn Clock, deadline: Duration) -> Duration {
+    loop {
+        let now = clock.now();
+        if now >= deadline || abort::requested() {
+            return now;
+        }
+        clock.spin();
+    }
+}
+
+#[inline]
+/// Delay/pause execution until `clock` reaches `deadline`.
+/// Returns the clock time the wait actually ended at.
+/// This function uses `Clock::sleep`, it returns early if the run is aborted.
+pub fn delay_until_sleep(clock: &dyn Clock, deadline: Duration) -> Duration {
+    sleep_until(clock, deadline);
+    clock.now()
+}
+
+/// Sleep until `clock` reaches `deadline`, at most [`ABORT_POLL`] at once,
+/// so the sleep ends early if the run is aborted.
+fn sleep_until(clock: &dyn Clock, deadline: Duration) {
+    while !abort::requested() {
+        match deadline.checked_sub(clock.now()) {
+            Some(remaining) if !remaining.is_zero() => clock.sleep(remaining.min(ABORT_POLL)),
+            _ => return,
+        }
+    }
+}
+
+#[inline]
+/// Delay/pause execution until `clock` reaches `deadline`.
+/// Returns the clock time the wait actually ended at.
+/// This function uses `Clock::sleep` until `margin` seconds before the deadline
+/// and a `busy-loop` for the remaining time, it returns early if the run is aborted.
+///
+/// # Panics
+/// Panics if passed margin is negativ.
+pub fn delay_until_hybrid(clock: &dyn Clock, deadline: Duration, margin: f64) -> Duration {
+    assert!(margin >= 0.0, "{margin}");
+    let margin = Duration::from_secs_f64(margin);
+    if let Some(sleep_end) = deadline.checked_sub(margin) {
+        sleep_until(clock, sleep_end);
+    }
+    delay_until_busy(clock, deadline)
 }
 
 #[test]
 fn test_delay_busy_precision_10_microseconds() {
+    use crate::clock::MockClock;
+
+    let clock = MockClock::new(Duration::from_micros(1));
     let dur = Duration::from_millis(10);
     let range = dur.as_micros() - 10..dur.as_micros() + 10;
     for _ in 0..100 {
-        let now = Instant::now();
-        delay_busy(0.01);
-        let elapsed = now.elapsed();
+        let now = clock.now();
+        delay_busy(&clock, 0.01);
+        let elapsed = clock.elapsed_since(now);
 
         assert!(range.contains(&elapsed.as_micros()));
     }
 }
+
+#[test]
+fn test_parse_spin_margin() {
+    assert_eq!(parse_spin_margin("0.002"), Ok(0.002));
+    assert_eq!(parse_spin_margin("0"), Ok(0.0));
+    assert!(parse_spin_margin("-0.001").is_err());
+    assert!(parse_spin_margin("NaN").is_err());
+    assert!(parse_spin_margin("inf").is_err());
+    assert!(parse_spin_margin("abc").is_err());
+}
+
+#[test]
+fn test_delay_hybrid_sleeps_until_margin() {
+    use crate::clock::MockClock;
+
+    // every spin iteration takes 1µs, so the number of spins is the time spent spinning
+    let clock = MockClock::new(Duration::from_micros(1));
+    let elapsed = delay_hybrid(&clock, 0.5, 0.002);
+
+    assert_eq!(elapsed, Duration::from_millis(500));
+
+    // deadline is closer than the margin, only spin
+    let start = clock.now();
+    let elapsed = delay_hybrid(&clock, 0.001, 0.002);
+    assert_eq!(elapsed, Duration::from_millis(1));
+    assert_eq!(clock.elapsed_since(start), Duration::from_millis(1));
+}
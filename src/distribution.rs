@@ -0,0 +1,215 @@
+use std::{fmt::Display, str::FromStr};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Distribution durations (in seconds) are drawn from.
+///
+/// Parsed from `<kind>:<args>`, e.g. `constant:0.1`, `uniform:0.05:0.15`, `exp:0.1`,
+/// `normal:0.1:0.02`, `sweep:0.01:0.2` or `file:delays.txt`. A plain number is a constant duration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Distribution {
+    /// Always the same duration
+    Constant(f64),
+    /// Uniformly distributed between min and max
+    Uniform { min: f64, max: f64 },
+    /// Exponentially distributed with the given mean (Poisson arrivals)
+    Exponential { mean: f64 },
+    /// Normally distributed with the given mean and standard deviation, negative durations are clamped to 0
+    Normal { mean: f64, std: f64 },
+    /// Linearly increasing from `from` to `to` over all samples
+    Sweep { from: f64, to: f64 },
+    /// Durations read from a file (one per line), repeated if there are more samples than lines
+    File { path: String, values: Vec<f64> },
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(value) = s.parse::<f64>() {
+            return Self::Constant(value).validated();
+        }
+
+        let (kind, args) = s.split_once(':').unwrap_or((s, ""));
+        if kind == "file" {
+            return Self::File {
+                path: args.to_owned(),
+                values: read_values(args)?,
+            }
+            .validated();
+        }
+
+        let numbers = args
+            .split(':')
+            .map(|arg| {
+                arg.parse::<f64>()
+                    .map_err(|_| format!("Invalid number '{arg}' in '{s}'"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let distribution = match (kind, numbers.as_slice()) {
+            ("constant", [value]) => Self::Constant(*value),
+            ("uniform", [min, max]) => Self::Uniform {
+                min: *min,
+                max: *max,
+            },
+            ("exp" | "exponential", [mean]) => Self::Exponential { mean: *mean },
+            ("normal", [mean, std]) => Self::Normal {
+                mean: *mean,
+                std: *std,
+            },
+            ("sweep", [from, to]) => Self::Sweep {
+                from: *from,
+                to: *to,
+            },
+            _ => {
+                return Err(format!(
+                    "Invalid distribution '{s}', expected one of: <secs>, constant:<secs>, uniform:<min>:<max>, exp:<mean>, normal:<mean>:<std>, sweep:<from>:<to>, file:<path>"
+                ))
+            }
+        };
+
+        distribution.validated()
+    }
+}
+
+impl Display for Distribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Constant(value) => write!(f, "constant:{value}"),
+            Self::Uniform { min, max } => write!(f, "uniform:{min}:{max}"),
+            Self::Exponential { mean } => write!(f, "exp:{mean}"),
+            Self::Normal { mean, std } => write!(f, "normal:{mean}:{std}"),
+            Self::Sweep { from, to } => write!(f, "sweep:{from}:{to}"),
+            Self::File { path, .. } => write!(f, "file:{path}"),
+        }
+    }
+}
+
+impl Distribution {
+    /// Check that the distribution can only produce non-negative durations.
+    fn validated(self) -> Result<Self, String> {
+        let valid = match &self {
+            Self::Constant(value) => *value >= 0.0,
+            Self::Uniform { min, max } => *min >= 0.0 && min <= max,
+            Self::Exponential { mean } => *mean > 0.0,
+            Self::Normal { mean, std } => *mean >= 0.0 && *std >= 0.0,
+            Self::Sweep { from, to } => *from >= 0.0 && *to >= 0.0,
+            Self::File { values, .. } => !values.is_empty() && values.iter().all(|v| *v >= 0.0),
+        };
+
+        if valid {
+            Ok(self)
+        } else {
+            Err(format!(
+                "Invalid distribution '{self}': durations must not be negative"
+            ))
+        }
+    }
+
+    /// Draw the `index`th of `count` durations from this distribution.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn draw(&self, rng: &mut StdRng, index: usize, count: usize) -> f64 {
+        match self {
+            Self::Constant(value) => *value,
+            Self::Uniform { min, max } if min >= max => *min,
+            Self::Uniform { min, max } => rng.gen_range(*min..*max),
+            // inverse transform sampling, `1 - u` is never 0
+            Self::Exponential { mean } => -mean * (1.0 - rng.gen::<f64>()).ln(),
+            // Box-Muller transform
+            Self::Normal { mean, std } => {
+                let (u, v) = (1.0 - rng.gen::<f64>(), rng.gen::<f64>());
+                let z = (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos();
+                (mean + std * z).max(0.0)
+            }
+            Self::Sweep { from, to } => {
+                let step = index as f64 / (count.max(2) - 1) as f64;
+                from + (to - from) * step
+            }
+            Self::File { values, .. } => values[index % values.len()],
+        }
+    }
+
+    /// Create a sampler drawing `count` durations from this distribution.
+    /// Random distributions are seeded with `seed`, so runs are reproducible.
+    pub fn sampler(&self, count: usize, seed: u64) -> Sampler<'_> {
+        Sampler {
+            distribution: self,
+            rng: StdRng::seed_from_u64(seed),
+            count,
+            index: 0,
+        }
+    }
+}
+
+/// Reads one duration per line, empty lines are skipped.
+fn read_values(path: &str) -> Result<Vec<f64>, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| format!("Failed to read '{path}': {err}"))?;
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            line.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid duration '{line}' in '{path}' (line {})", n + 1))
+        })
+        .collect()
+}
+
+/// Draws durations from a [`Distribution`].
+#[derive(Debug)]
+pub struct Sampler<'a> {
+    distribution: &'a Distribution,
+    rng: StdRng,
+    count: usize,
+    index: usize,
+}
+
+impl Iterator for Sampler<'_> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let value = self
+            .distribution
+            .draw(&mut self.rng, self.index, self.count);
+        self.index += 1;
+        Some(value)
+    }
+}
+
+#[test]
+fn test_distributions() {
+    let parse = |s: &str| s.parse::<Distribution>().unwrap();
+
+    assert_eq!(parse("0.1"), Distribution::Constant(0.1));
+    assert!("uniform:0.2:0.1".parse::<Distribution>().is_err());
+    assert!("gauss:0.1".parse::<Distribution>().is_err());
+
+    let sweep = parse("sweep:0.0:1.0").sampler(5, 0).collect::<Vec<_>>();
+    assert_eq!(sweep, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+
+    // same seed, same durations
+    let uniform = parse("uniform:0.05:0.15");
+    let a = uniform.sampler(100, 42).collect::<Vec<_>>();
+    let b = uniform.sampler(100, 42).collect::<Vec<_>>();
+    assert_eq!(a, b);
+    assert!(a.iter().all(|v| (0.05..0.15).contains(v)));
+
+    let normal = parse("normal:0.1:0.02")
+        .sampler(10_000, 1)
+        .collect::<Vec<_>>();
+    let mean = normal.iter().sum::<f64>() / 10_000.0;
+    assert!((mean - 0.1).abs() < 0.005, "{mean}");
+    assert!(normal.iter().all(|v| *v >= 0.0));
+
+    let exp = parse("exp:0.1").sampler(10_000, 1).collect::<Vec<_>>();
+    let mean = exp.iter().sum::<f64>() / 10_000.0;
+    assert!((mean - 0.1).abs() < 0.01, "{mean}");
+}
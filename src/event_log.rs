@@ -0,0 +1,270 @@
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{clock::Clock, scheduler::Fired};
+
+/// Direction of a simulated input event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Down,
+    Up,
+}
+
+/// Timing of one injected input event.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EventRecord {
+    /// Password index or input file the event belongs to
+    pub sample: String,
+    /// Index of the event inside its sample
+    pub index: usize,
+    pub key: String,
+    pub direction: Direction,
+    /// Seconds after the sample start the event was scheduled at (empty if not scheduled)
+    pub scheduled: Option<f64>,
+    /// Seconds after the sample start the event was actually executed at
+    pub actual: f64,
+    /// Seconds spent inside the enigo call
+    pub took: f64,
+}
+
+impl EventRecord {
+    /// Create a record for an event executed by a [`crate::scheduler::Scheduler`].
+    pub fn from_fired<K: Debug>(
+        sample: &str,
+        index: usize,
+        key: K,
+        direction: Direction,
+        fired: &Fired,
+    ) -> Self {
+        Self {
+            sample: sample.to_owned(),
+            index,
+            key: format!("{key:?}"),
+            direction,
+            scheduled: Some(fired.scheduled),
+            actual: fired.actual,
+            took: fired.took,
+        }
+    }
+
+    /// Create a record for an event that was not scheduled, but executed at clock time `fired`.
+    /// Has to be called directly after the event was executed.
+    pub fn unscheduled<K: Debug>(
+        sample: &str,
+        index: usize,
+        key: K,
+        direction: Direction,
+        clock: &dyn Clock,
+        start: Duration,
+        fired: Duration,
+    ) -> Self {
+        Self {
+            sample: sample.to_owned(),
+            index,
+            key: format!("{key:?}"),
+            direction,
+            scheduled: None,
+            actual: fired.saturating_sub(start).as_secs_f64(),
+            took: clock.elapsed_since(fired).as_secs_f64(),
+        }
+    }
+}
+
+/// Writer for per-event injection logs.
+/// Files ending with `.jsonl` are written as JSON lines, all other files as CSV.
+pub enum EventLog {
+    Csv(Box<csv::Writer<File>>),
+    Jsonl(BufWriter<File>),
+}
+
+impl EventLog {
+    /// Create (or truncate) the event log at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if file could not be created.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open(path, false)
+    }
+
+    /// Create (or truncate) the event log at `path`.
+    /// If `append` is set, records are appended to an existing log instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if file could not be opened.
+    pub fn open<P: AsRef<Path>>(path: P, append: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let file = File::options()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+
+        if path.extension().is_some_and(|ext| ext == "jsonl") {
+            Ok(Self::Jsonl(BufWriter::new(file)))
+        } else {
+            // only write the header to an empty file
+            let writer = csv::WriterBuilder::new()
+                .has_headers(file.metadata()?.len() == 0)
+                .from_writer(file);
+            Ok(Self::Csv(Box::new(writer)))
+        }
+    }
+
+    /// Create the event log at `path`, if a path is given.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if file could not be created.
+    pub fn create_optional<P: AsRef<Path>>(
+        path: Option<P>,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        path.map(Self::create).transpose()
+    }
+
+    /// Create (or append to, if `append` is set) the event log at `path`, if a path is given.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if file could not be opened.
+    pub fn open_optional<P: AsRef<Path>>(
+        path: Option<P>,
+        append: bool,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        path.map(|path| Self::open(path, append)).transpose()
+    }
+
+    /// Drop the records written after the last record of `sample` from the event log at `path`,
+    /// e.g. of a password whose output row was never written. All records are dropped if `sample` is `None`,
+    /// if the log has no record of `sample`, only a partially written last line is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the log could not be read or truncated.
+    pub fn truncate_after<P: AsRef<Path>>(
+        path: P,
+        sample: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(());
+        }
+        let content = std::fs::read_to_string(path)?;
+        let jsonl = path.extension().is_some_and(|ext| ext == "jsonl");
+        let sample_of = |line: &str| {
+            if jsonl {
+                let record = serde_json::from_str::<serde_json::Value>(line).ok()?;
+                record.get("sample")?.as_str().map(str::to_owned)
+            } else {
+                line.split(',').next().map(str::to_owned)
+            }
+        };
+
+        // ends of the CSV header, the last complete line and the last record of `sample`
+        let (mut header, mut complete, mut last) = (0, 0, None);
+        for (n, line) in content.split_inclusive('\n').enumerate() {
+            if !line.ends_with('\n') {
+                break;
+            }
+            complete += line.len();
+            if n == 0 && !jsonl {
+                header = complete;
+            } else if sample.is_some() && sample_of(line.trim_end()).as_deref() == sample {
+                last = Some(complete);
+            }
+        }
+
+        let keep = match sample {
+            Some(_) => last.unwrap_or(complete),
+            None => header,
+        };
+        if keep < content.len() {
+            println!(
+                "Dropping {} bytes of uncompleted records from {}",
+                content.len() - keep,
+                path.display()
+            );
+            File::options()
+                .write(true)
+                .open(path)?
+                .set_len(keep as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Write all `records` to the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if writing to the file failed.
+    pub fn write_all(&mut self, records: &[EventRecord]) -> Result<(), Box<dyn std::error::Error>> {
+        for record in records {
+            match self {
+                Self::Csv(writer) => writer.serialize(record)?,
+                Self::Jsonl(writer) => {
+                    serde_json::to_writer(&mut *writer, record)?;
+                    writeln!(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush all buffered records to disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if writing to the file failed.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Csv(writer) => writer.flush(),
+            Self::Jsonl(writer) => writer.flush(),
+        }
+    }
+}
+
+#[test]
+fn test_truncate_after() {
+    let dir = std::env::temp_dir().join(format!("browser-timer-rs-log-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let record = |sample: &str| EventRecord {
+        sample: sample.to_owned(),
+        index: 0,
+        key: "Layout(',')".to_owned(),
+        direction: Direction::Down,
+        scheduled: None,
+        actual: 0.0,
+        took: 0.0,
+    };
+
+    // the CSV log has a header line
+    for (name, header) in [("events.csv", 1), ("events.jsonl", 0)] {
+        let path = dir.join(name);
+        let mut log = EventLog::create(&path).unwrap();
+        log.write_all(&[record("1"), record("1"), record("2")])
+            .unwrap();
+        log.flush().unwrap();
+        drop(log);
+        let complete = std::fs::read_to_string(&path).unwrap();
+        let lines = |path: &Path| std::fs::read_to_string(path).unwrap().lines().count();
+
+        // the records of password 2 and a partial line are dropped
+        std::fs::write(&path, format!("{complete}{{\"sample\"")).unwrap();
+        EventLog::truncate_after(&path, Some("1")).unwrap();
+        assert_eq!(lines(&path), header + 2);
+        EventLog::truncate_after(&path, Some("3")).unwrap();
+        assert_eq!(lines(&path), header + 2);
+        EventLog::truncate_after(&path, None).unwrap();
+        assert_eq!(lines(&path), header);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
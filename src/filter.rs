@@ -0,0 +1,134 @@
+use std::{fmt::Display, str::FromStr};
+
+use regex::Regex;
+
+/// Inclusive range of indices, e.g. `3`, `1-10` or `5-` (open end).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl IndexRange {
+    pub fn contains(&self, value: u64) -> bool {
+        value >= self.start && self.end.map_or(true, |end| value <= end)
+    }
+}
+
+impl FromStr for IndexRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid range '{s}', expected '<n>', '<from>-<to>' or '<from>-'");
+        let parse = |n: &str| n.trim().parse::<u64>().map_err(|_| invalid());
+
+        let range = match s.split_once('-') {
+            None => Self {
+                start: parse(s)?,
+                end: Some(parse(s)?),
+            },
+            Some((start, end)) if end.trim().is_empty() => Self {
+                start: parse(start)?,
+                end: None,
+            },
+            Some((start, end)) => Self {
+                start: parse(start)?,
+                end: Some(parse(end)?),
+            },
+        };
+
+        if range.end.is_some_and(|end| end < range.start) {
+            return Err(invalid());
+        }
+        Ok(range)
+    }
+}
+
+impl Display for IndexRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.end {
+            Some(end) if end == self.start => write!(f, "{end}"),
+            Some(end) => write!(f, "{}-{end}", self.start),
+            None => write!(f, "{}-", self.start),
+        }
+    }
+}
+
+/// Command line options selecting dataset rows by subject, session and repetition.
+#[derive(Debug, Clone, clap::Args)]
+pub struct RowFilter {
+    /// Specifies a comma separated list of subjects to simulate, e.g. 's002,s003'
+    #[arg(long, value_delimiter = ',')]
+    pub subjects: Vec<String>,
+
+    /// Specifies a regex subjects have to match to be simulated, e.g. '^s0(0[2-9]|10)$'
+    #[arg(long)]
+    pub subject_regex: Option<Regex>,
+
+    /// Specifies which sessions to simulate, e.g. '1-4'
+    #[arg(long)]
+    pub sessions: Option<IndexRange>,
+
+    /// Specifies which repetitions (of each session) to simulate, e.g. '1-10'
+    #[arg(long)]
+    pub reps: Option<IndexRange>,
+}
+
+impl RowFilter {
+    /// If any filter is set.
+    pub fn is_active(&self) -> bool {
+        !self.subjects.is_empty()
+            || self.subject_regex.is_some()
+            || self.sessions.is_some()
+            || self.reps.is_some()
+    }
+
+    /// If a row of `subject`, `session` and `rep` passes all filters.
+    pub fn matches(&self, subject: &str, session: u64, rep: u64) -> bool {
+        (self.subjects.is_empty() || self.subjects.iter().any(|s| s == subject))
+            && self
+                .subject_regex
+                .as_ref()
+                .map_or(true, |regex| regex.is_match(subject))
+            && self.sessions.map_or(true, |range| range.contains(session))
+            && self.reps.map_or(true, |range| range.contains(rep))
+    }
+}
+
+impl Display for RowFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut filters = Vec::new();
+        if !self.subjects.is_empty() {
+            filters.push(format!("subjects={}", self.subjects.join(",")));
+        }
+        if let Some(regex) = &self.subject_regex {
+            filters.push(format!("subject-regex={regex}"));
+        }
+        if let Some(sessions) = self.sessions {
+            filters.push(format!("sessions={sessions}"));
+        }
+        if let Some(reps) = self.reps {
+            filters.push(format!("reps={reps}"));
+        }
+        write!(f, "{}", filters.join(" "))
+    }
+}
+
+#[test]
+fn test_row_filter() {
+    let filter = RowFilter {
+        subjects: Vec::new(),
+        subject_regex: Some(Regex::new("^s0(0[2-9]|10)$").unwrap()),
+        sessions: Some("2-".parse().unwrap()),
+        reps: Some("1-10".parse().unwrap()),
+    };
+
+    assert!(filter.matches("s005", 2, 10));
+    assert!(filter.matches("s010", 8, 1));
+    assert!(!filter.matches("s011", 2, 1));
+    assert!(!filter.matches("s005", 1, 1));
+    assert!(!filter.matches("s005", 2, 11));
+
+    assert!("5-3".parse::<IndexRange>().is_err());
+    assert_eq!("4".parse::<IndexRange>().unwrap().to_string(), "4");
+}
@@ -1,53 +1,107 @@
 use std::{
-    fmt::Display,
-    fs::File,
-    io::{BufRead, BufReader, BufWriter, Write},
+    collections::BTreeMap,
+    io::{BufWriter, Write},
     path::Path,
-    time::{Duration, Instant},
+    sync::Arc,
 };
 
 use enigo::{Enigo, Key, KeyboardControllable};
 
 use crate::{
-    delay::{delay_busy, delay_sleep},
+    abort::{self, HeldKeys},
+    calibration::Compensation,
+    clock::Clock,
+    dataset::{DatasetFormat, Recording},
+    delay::{delay_sleep, DelayStrategy},
+    event_log::{Direction, EventLog, EventRecord},
+    free_text_data::{
+        create_task_list, simulated_key, Sample, ShiftMode, Task, TaskList, WrapOptions,
+    },
+    hold::HoldModel,
+    pause::Pauses,
+    realtime::RealtimeOptions,
+    run_info::RunInfo,
+    scheduler::Scheduler,
+    stats::Summary,
     DOWNLOAD_KEY,
 };
 
-#[allow(clippy::cast_precision_loss)]
-pub fn free_text_simulation<R: AsRef<Path>>(
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::too_many_arguments,
+    clippy::too_many_lines
+)]
+pub fn free_text_simulation<R: AsRef<Path>, O: AsRef<Path>>(
     input_file_desc: R,
+    format: DatasetFormat,
+    output_dir: O,
     warmup: bool,
+    strategy: DelayStrategy,
+    clock: &Arc<dyn Clock>,
+    event_log: Option<&str>,
+    realtime: RealtimeOptions,
+    wrap: WrapOptions,
+    shift_mode: ShiftMode,
+    mut holds: HoldModel,
+    compensation: Option<&Compensation>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("[Free-Text Simulation]");
-    // get all input files
-    let files = get_input_files(input_file_desc).unwrap();
+    // read all input files
+    let samples = format
+        .read(input_file_desc)?
+        .into_iter()
+        .map(Recording::into_sample)
+        .collect();
+    let (samples, wraps) = wrap.apply(samples)?;
 
     println!("Read all input files...");
 
-    const OUT_DIR: &str = "./free-text-output";
     // create output dir
-    if std::fs::remove_dir_all("./free-text-output").is_err() {
-        println!("Output folder not removed");
-    }
-    std::fs::create_dir_all("./free-text-output")?;
+    let output_dir = output_dir.as_ref();
+    std::fs::create_dir_all(output_dir)?;
+    println!("Writing output to {}", output_dir.display());
+
+    // pin thread and raise its priority, record what is really in effect
+    let mut run_info = RunInfo::at(output_dir.join("run.json"));
+    run_info.set("realtime", realtime.apply())?;
+    run_info.set("wrap", wrap)?;
+    run_info.set("shift_mode", shift_mode)?;
+    run_info.set("hold", holds.info())?;
+    run_info.set("wraps", wraps)?;
+    run_info.set("compensation", compensation)?;
+    run_info.save()?;
+
+    // open the per-event log
+    let mut event_log = EventLog::create_optional(event_log)?;
 
     println!("Waiting for use to be ready (5 secs) ...");
-    std::thread::sleep(Duration::from_secs_f64(5.0));
+    delay_sleep(&**clock, 5.0);
     println!("Start simulating...");
 
-    let len = files.len();
+    let len = samples.len();
+    let mut unmapped = Unmapped::new();
+    let mut pauses = Pauses::new(clock.now());
 
     // read each file to task list
-    for (i, (file, f_name)) in files.into_iter().enumerate() {
+    for (
+        i,
+        Sample {
+            name: f_name,
+            keystrokes,
+        },
+    ) in samples.into_iter().enumerate()
+    {
+        if pauses.wait(&**clock, &f_name) {
+            run_info.set("pauses", pauses.records())?;
+            run_info.save()?;
+        }
         println!(
             "Starting file: [{f_name}]  {i} / {len} ({:.2}%)",
             (i as f32 / len as f32) * 100.0
         );
         let mut keyboard = Enigo::new();
 
-        let reader = BufReader::new(file);
-
-        let mut path = std::path::Path::new(OUT_DIR).join(f_name);
+        let mut path = output_dir.join(&f_name);
         assert!(path.set_extension("csv"));
 
         let mut out_f = BufWriter::new(
@@ -59,15 +113,27 @@ pub fn free_text_simulation<R: AsRef<Path>>(
                 .open(path)?,
         );
 
-        // write row name
-        writeln!(out_f, "key,")?;
-        out_f.flush()?;
-
-        // read file to vec
-        let raw: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
-
         // create task list
-        let tasks = create_task_list(&raw, &mut out_f).unwrap();
+        let TaskList {
+            tasks,
+            holds: hold_times,
+            unmapped: codes,
+        } = create_task_list(&keystrokes, shift_mode, &mut holds);
+        record_unmapped(
+            &mut run_info,
+            &mut unmapped,
+            &f_name,
+            codes,
+            keystrokes.len(),
+        )?;
+
+        // write row name, key codes and synthesized hold times (empty for keys that are not simulated)
+        writeln!(out_f, "key,hold,")?;
+        for (keystroke, hold) in keystrokes.iter().zip(&hold_times) {
+            let hold = hold.map(|hold| hold.to_string()).unwrap_or_default();
+            writeln!(out_f, "{},{hold},", keystroke.code)?;
+        }
+        out_f.flush()?;
 
         // warm up
         if warmup {
@@ -77,210 +143,153 @@ pub fn free_text_simulation<R: AsRef<Path>>(
             }
         }
 
-        // execute task list
-        let mut last = Option::<Instant>::None;
-        let mut last_waited = 0.0;
-        for task in tasks {
-            match task {
-                // wait
-                Task::Wait(dur) => {
-                    delay_busy(dur);
-
-                    last_waited = dur;
-                }
-                // press key
-                Task::Key(key) => {
-                    #[cfg(target_os = "macos")]
-                    {
-                        keyboard.key_down(Key::Layout('a'));
-                        keyboard.key_up(Key::Layout('a'));
-                    }
-
-                    #[cfg(not(target_os = "macos"))]
-                    {
-                        keyboard.key_down(key);
-                        keyboard.key_up(key);
-                    }
+        let records = execute_tasks(
+            &mut keyboard,
+            &tasks,
+            clock,
+            strategy,
+            compensation,
+            &f_name,
+        );
 
-                    let last_elapsed = if let Some(l) = last {
-                        let out = l.elapsed().as_secs_f64();
-                        last = Some(Instant::now());
-                        out
-                    } else {
-                        last = Some(Instant::now());
-                        0.0
-                    };
+        // write per-event timing data to event log
+        if let Some(log) = &mut event_log {
+            log.write_all(&records)?;
+            log.flush()?;
+        }
 
-                    debug_assert!((last_elapsed - last_waited).abs() <= 0.001);
-                }
-            }
+        if abort::requested() {
+            return interrupt(&mut run_info, &f_name, i, len);
         }
 
         // sleep before finishing this queue
-        delay_sleep(5.0);
+        delay_sleep(&**clock, 5.0);
 
         // if task list is finished, trigger download
         keyboard.key_click(DOWNLOAD_KEY);
         out_f.flush()?;
         drop(out_f);
 
-        std::thread::sleep(Duration::from_secs_f64(4.0));
+        delay_sleep(&**clock, 4.0);
     }
 
     Ok(())
 }
 
-#[allow(clippy::cast_precision_loss)]
-fn create_task_list(
-    raw: &Vec<String>,
-    key_file: &mut BufWriter<File>,
-) -> Result<Vec<Task>, Box<dyn std::error::Error>> {
-    debug_assert!(raw.len() % 2 == 0, "Always a pair of timestamp and key.");
-    let mut out = Vec::with_capacity(raw.len() / 2);
-
-    let timestamps = raw
-        .iter()
-        .step_by(2)
-        .map(|t| t.parse::<u64>())
-        .collect::<Result<Vec<_>, _>>()
-        .unwrap();
-
-    let mut iter = raw.iter();
-    _ = iter.next();
-
-    let keys = iter
-        .step_by(2)
-        .map(|key_raw| {
-            let key_u8 = key_raw.parse::<u8>()?;
-            writeln!(key_file, "{key_u8},")?;
-            Ok::<Key, Box<dyn std::error::Error>>(map_u8_key(key_u8))
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    key_file.flush()?;
-    assert_eq!(timestamps.len() + keys.len(), raw.len());
-    assert_eq!(timestamps.len(), keys.len());
-
-    // calculate wait times after each key
-    let mut diffs = Vec::new();
-    let mut last = None::<u64>;
-
-    for current in &timestamps {
-        let c = *current;
-
-        // First element
-        if last.is_none() {
-            diffs.push(0);
-            last = Some(c);
-            continue;
-        }
-
-        let last_uwr = last.unwrap();
-
-        // monotonic increment
-        if last_uwr < c {
-            let dif = current - last_uwr;
-            diffs.push(dif);
-            last = Some(c);
-            continue;
-        }
-
-        // reset timestamps
-        if last_uwr > c {
-            let to_reset = 100_000 - last_uwr;
-            let dif = to_reset + current;
-            diffs.push(dif);
-            last = Some(c);
-            continue;
-        }
-
-        // no delay to next key
-        if last_uwr == c {
-            diffs.push(0);
-            continue;
-        }
-
-        unreachable!()
+/// Key codes that are not simulated and how often they were found, per sample.
+pub type Unmapped = BTreeMap<String, BTreeMap<u8, usize>>;
+
+/// Report the key codes of sample `name` (with `keys` keys in total) that are not simulated,
+/// and record them with the run.
+///
+/// # Errors
+///
+/// Returns an error, if the run info could not be saved.
+pub fn record_unmapped(
+    run_info: &mut RunInfo,
+    unmapped: &mut Unmapped,
+    name: &str,
+    codes: BTreeMap<u8, usize>,
+    keys: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if codes.is_empty() {
+        return Ok(());
     }
 
-    assert_eq!(diffs.len(), keys.len());
-
-    let total_time = diffs.iter().sum::<u64>();
+    let histogram = codes
+        .iter()
+        .map(|(code, count)| format!("{code}: {count}"))
+        .collect::<Vec<_>>();
     println!(
-        "Task Queue takes: {:.2}min",
-        (total_time as f32 / 1000.0) / 60.0
+        "Unmapped key codes ({} of {keys} keys): {}",
+        codes.values().sum::<usize>(),
+        histogram.join(", ")
     );
-
-    for (t, k) in diffs.into_iter().zip(keys.into_iter()) {
-        if t != 0 {
-            out.push(Task::Wait(t as f64 / 1000.0)); // convert to seconds
-        }
-
-        out.push(Task::Key(k));
-    }
-
-    Ok(out)
+    unmapped.insert(name.to_owned(), codes);
+    run_info.set("unmapped", &unmapped)?;
+    run_info.save()
 }
 
-fn get_input_files<R: AsRef<Path>>(
-    input_file_desc: R,
-) -> Result<Vec<(File, String)>, Box<dyn std::error::Error>> {
-    let file = File::open(input_file_desc.as_ref())?;
-
-    let reader = BufReader::new(file);
-    let file_paths: Vec<String> = serde_json::from_reader(reader)?;
-
-    let mut out = Vec::with_capacity(file_paths.len());
-    for file_p in &file_paths {
-        let f = std::fs::File::open(file_p)?;
-
-        let fp = file_p.split('/').last().unwrap();
-        out.push((f, fp.to_owned()));
-    }
-
-    Ok(out)
-}
-
-#[inline(always)]
-fn map_u8_key(c: u8) -> Key {
-    // uppercase letters to lowercase letters
-    if (65..=90).contains(&c) {
-        return Key::Layout((c + 32) as char);
+/// Execute the task list of sample `name`, every key is scheduled against the same anchor,
+/// shifted earlier by its latency (if compensated).
+/// Injecting stops as soon as the run is aborted, keys still held down are released then.
+/// Returns the timing of every injected event.
+pub fn execute_tasks(
+    keyboard: &mut Enigo,
+    tasks: &[Task],
+    clock: &Arc<dyn Clock>,
+    strategy: DelayStrategy,
+    compensation: Option<&Compensation>,
+    name: &str,
+) -> Vec<EventRecord> {
+    let lead = compensation.map_or(0.0, |c| c.lead);
+    let scheduler = Scheduler::start_with_lead(clock, strategy, lead);
+    let latency = |key, direction| compensation.map_or(0.0, |c| c.offset(key, direction));
+    let mut offset = 0.0;
+    let mut lateness = Vec::with_capacity(tasks.len());
+    let mut records = Vec::new();
+    // keys held down by the task list, released if the run is aborted
+    let mut held = HeldKeys::default();
+    'tasks: for task in tasks {
+        let Some((key, directions)) = task.key_events() else {
+            // wait
+            if let Task::Wait(dur) = task {
+                offset += dur;
+            }
+            continue;
+        };
+
+        let key = simulated_key(key);
+        for &direction in directions {
+            let Some(fired) =
+                scheduler.fire_at(offset - latency(key, direction), || match direction {
+                    Direction::Down => keyboard.key_down(key),
+                    Direction::Up => keyboard.key_up(key),
+                })
+            else {
+                break 'tasks;
+            };
+            held.track(key, direction);
+            if direction == Direction::Down {
+                lateness.push(fired.lateness());
+            }
+            records.push(EventRecord::from_fired(
+                name,
+                records.len(),
+                key,
+                direction,
+                &fired,
+            ));
+        }
     }
 
-    // lowercase letters
-    if (97..=122).contains(&c) {
-        return Key::Layout(c as char);
+    if abort::requested() {
+        held.release_all(keyboard);
     }
-
-    // numbers
-    if (48..=57).contains(&c) {
-        return Key::Layout(c as char);
+    if let Some(summary) = Summary::new(&lateness) {
+        println!("Key lateness: {summary}");
     }
-
-    match c {
-        8 => Key::Backspace,
-        13 => Key::Layout(c as char),
-        32 => Key::Space,
-        44 => Key::Layout(','),
-        45 => Key::Layout('-'),
-        46 => Key::Layout('.'),
-        _ => Key::Layout('#'),
-    }
-}
-
-#[derive(Debug)]
-enum Task {
-    Wait(f64),
-    Key(Key),
+    records
 }
 
-#[derive(Debug)]
-struct NoCharFoundError;
-
-impl std::error::Error for NoCharFoundError {}
-
-impl Display for NoCharFoundError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Given Key Code could not be parsed as char")
-    }
+/// Record that the run was interrupted in sample `name`, after `completed` of `len` samples.
+///
+/// # Errors
+///
+/// Always returns an error describing the interruption,
+/// or the error saving the run info.
+pub fn interrupt(
+    run_info: &mut RunInfo,
+    name: &str,
+    completed: usize,
+    len: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_info.set(
+        "interrupted",
+        serde_json::json!({ "file": name, "completed": completed }),
+    )?;
+    run_info.save()?;
+    Err(Box::new(crate::Error(format!(
+        "Interrupted in file {name} ({completed} / {len})"
+    ))))
 }
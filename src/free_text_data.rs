@@ -0,0 +1,1015 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use enigo::Key;
+
+use crate::{event_log::Direction, hold::HoldModel};
+
+/// Error reading the free-text dataset, pointing to the file (and line) that could not be read.
+#[derive(Debug)]
+pub enum ParseError {
+    /// A file could not be read
+    Io {
+        file: PathBuf,
+        source: std::io::Error,
+    },
+    /// The input description is not a JSON list of file paths
+    Description {
+        file: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A timestamp line does not hold a timestamp (in ms)
+    Timestamp {
+        file: String,
+        line: usize,
+        value: String,
+    },
+    /// A key line does not hold a key code (0 - 255)
+    KeyCode {
+        file: String,
+        line: usize,
+        value: String,
+    },
+    /// The last timestamp of a file has no key code
+    Unpaired { file: String, line: usize },
+    /// A line or record of a dataset in another format can not be read
+    Record {
+        file: String,
+        line: usize,
+        reason: String,
+    },
+    /// A timestamp goes backwards (with the 'error' wrap strategy)
+    Backwards {
+        file: String,
+        line: Option<usize>,
+        from: u64,
+        to: u64,
+    },
+    /// A sample wraps around, but one of its timestamps is not below the modulus
+    WrapMismatch {
+        file: String,
+        line: Option<usize>,
+        timestamp: u64,
+        modulus: u64,
+    },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { file, source } => write!(f, "Failed to read {}: {source}", file.display()),
+            Self::Description { file, source } => write!(
+                f,
+                "Invalid input description {}, expected a JSON list of file paths: {source}",
+                file.display()
+            ),
+            Self::Timestamp { file, line, value } => {
+                write!(f, "Invalid timestamp '{value}' in {file} (line {line})")
+            }
+            Self::KeyCode { file, line, value } => {
+                write!(f, "Invalid key code '{value}' in {file} (line {line})")
+            }
+            Self::Unpaired { file, line } => {
+                write!(f, "Timestamp without key code in {file} (line {line})")
+            }
+            Self::Record { file, line, reason } => {
+                write!(f, "Invalid record in {file} (line {line}): {reason}")
+            }
+            Self::Backwards {
+                file,
+                line,
+                from,
+                to,
+            } => write!(
+                f,
+                "Timestamp goes backwards from {from} to {to} in {file}{}",
+                at_line(*line)
+            ),
+            Self::WrapMismatch {
+                file,
+                line,
+                timestamp,
+                modulus,
+            } => write!(
+                f,
+                "Timestamp {timestamp} in {file}{} does not fit a wrap-around at {modulus}",
+                at_line(*line)
+            ),
+        }
+    }
+}
+
+/// ` (line <n>)`, empty if the line is not known.
+fn at_line(line: Option<usize>) -> String {
+    line.map(|line| format!(" (line {line})"))
+        .unwrap_or_default()
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Description { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// One key press of a free-text sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keystroke {
+    /// Timestamp in ms, wraps around in the raw data
+    pub timestamp: u64,
+    /// Key code (ASCII, 8 = backspace)
+    pub code: u8,
+    /// Recorded time (in ms) the key was held down, `None` if only the keydown was recorded
+    pub hold: Option<u64>,
+    /// Line of the dataset file the key press was read from, if known
+    pub line: Option<usize>,
+}
+
+/// One file of the free-text dataset ('./KEYSTROKE-SAMPLES-31-USERS/').
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// File name, without its directories
+    pub name: String,
+    pub keystrokes: Vec<Keystroke>,
+}
+
+/// Read every file listed in the input description (a JSON list of file paths).
+/// All files are read up front, so a malformed file is found before the simulation starts.
+///
+/// # Errors
+///
+/// Returns an error, if the description or any listed file could not be read or parsed.
+pub fn read_dataset<P: AsRef<Path>>(input_file_desc: P) -> Result<Vec<Sample>, ParseError> {
+    let path = input_file_desc.as_ref();
+    let file = File::open(path).map_err(|source| ParseError::Io {
+        file: path.to_owned(),
+        source,
+    })?;
+    let file_paths: Vec<PathBuf> =
+        serde_json::from_reader(BufReader::new(file)).map_err(|source| {
+            ParseError::Description {
+                file: path.to_owned(),
+                source,
+            }
+        })?;
+
+    file_paths.iter().map(read_sample).collect()
+}
+
+/// Read one file of the free-text dataset.
+///
+/// # Errors
+///
+/// Returns an error, if the file could not be read or parsed.
+pub fn read_sample<P: AsRef<Path>>(path: P) -> Result<Sample, ParseError> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|source| ParseError::Io {
+        file: path.to_owned(),
+        source,
+    })?;
+
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let keystrokes = parse_sample(&name, &content)?;
+    Ok(Sample { name, keystrokes })
+}
+
+/// Parse the content of a sample file, alternating lines of timestamp and key code.
+/// Trailing empty lines are ignored.
+///
+/// # Errors
+///
+/// Returns an error, if a line can not be parsed or the last timestamp has no key code.
+pub fn parse_sample(name: &str, content: &str) -> Result<Vec<Keystroke>, ParseError> {
+    let lines = content
+        .trim_end()
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>();
+
+    lines
+        .chunks(2)
+        .enumerate()
+        .map(|(n, pair)| {
+            // line numbers start at 1
+            let line = n * 2 + 1;
+            let [timestamp, code] = pair else {
+                return Err(ParseError::Unpaired {
+                    file: name.to_owned(),
+                    line,
+                });
+            };
+
+            let timestamp = timestamp
+                .parse::<u64>()
+                .map_err(|_| ParseError::Timestamp {
+                    file: name.to_owned(),
+                    line,
+                    value: (*timestamp).to_owned(),
+                })?;
+            let code = code.parse::<u8>().map_err(|_| ParseError::KeyCode {
+                file: name.to_owned(),
+                line: line + 1,
+                value: (*code).to_owned(),
+            })?;
+            Ok(Keystroke {
+                timestamp,
+                code,
+                hold: None,
+                line: Some(line),
+            })
+        })
+        .collect()
+}
+
+/// How timestamps going backwards in a sample are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapStrategy {
+    /// The timestamp wrapped around at the modulus, if the gap this implies is plausible
+    /// (see '--max-wrap-gap'), other timestamps going backwards are clamped
+    Wrap,
+    /// There is no delay before the key
+    Clamp,
+    /// The sample is split into separate sessions at the key
+    Split,
+    /// Stop with an error
+    Error,
+}
+
+/// Command line options for timestamps wrapping around in free-text data.
+#[derive(Debug, Clone, Copy, clap::Args, serde::Serialize)]
+pub struct WrapOptions {
+    /// Specifies the modulus (in ms) timestamps of the free-text data wrap around at
+    #[arg(long, default_value_t = 100_000)]
+    pub wrap_modulus: u64,
+
+    /// Specifies how timestamps going backwards are handled
+    #[arg(long, value_enum, default_value_t = WrapStrategy::Wrap)]
+    pub wrap_strategy: WrapStrategy,
+
+    /// Specifies the longest gap (in ms) a wrap-around may imply, timestamps going further back
+    /// are out of order and clamped ('wrap' strategy only)
+    #[arg(long, default_value_t = 10_000)]
+    pub max_wrap_gap: u64,
+}
+
+/// Timestamp going backwards in a sample.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WrapEvent {
+    pub file: String,
+    /// Line of the timestamp going backwards, if known
+    pub line: Option<usize>,
+    pub from: u64,
+    pub to: u64,
+    /// How the timestamp was handled
+    pub strategy: WrapStrategy,
+}
+
+impl WrapOptions {
+    /// Make the timestamps of all samples increasing, handling every timestamp going backwards
+    /// with the selected strategy. Every such event is reported and returned.
+    ///
+    /// With the 'wrap' strategy, a step back is only a wrap-around if the gap
+    /// from the old timestamp to the modulus and on to the new one is at most `max_wrap_gap`,
+    /// otherwise the keys are out of order and the step is clamped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if a timestamp goes backwards with the 'error' strategy,
+    /// or can not have wrapped around at the modulus with the 'wrap' strategy.
+    pub fn apply(&self, samples: Vec<Sample>) -> Result<(Vec<Sample>, Vec<WrapEvent>), ParseError> {
+        let mut out = Vec::with_capacity(samples.len());
+        let mut events = Vec::new();
+
+        for sample in samples {
+            let found = events.len();
+            let mut sessions = vec![Vec::with_capacity(sample.keystrokes.len())];
+            // added to all following raw timestamps
+            let mut offset = 0;
+            let mut last = None::<u64>;
+            // gap between `from` and `to`, if `to` wrapped around after it
+            let wrapped = |from: u64, to: u64| {
+                to < from && self.wrap_modulus.saturating_sub(from) + to <= self.max_wrap_gap
+            };
+            // timestamps of a sample that wraps around are all taken modulo the modulus
+            let wraps = self.wrap_strategy == WrapStrategy::Wrap
+                && sample
+                    .keystrokes
+                    .windows(2)
+                    .any(|pair| wrapped(pair[0].timestamp, pair[1].timestamp));
+
+            for (n, keystroke) in sample.keystrokes.iter().enumerate() {
+                let (from, to) = (last.unwrap_or_default(), keystroke.timestamp);
+                last = Some(to);
+                let line = keystroke.line;
+
+                if wraps && to >= self.wrap_modulus {
+                    return Err(ParseError::WrapMismatch {
+                        file: sample.name,
+                        line,
+                        timestamp: to,
+                        modulus: self.wrap_modulus,
+                    });
+                }
+
+                if n > 0 && to < from {
+                    // out of order keys are never a wrap-around
+                    let strategy = match self.wrap_strategy {
+                        WrapStrategy::Wrap if !wrapped(from, to) => WrapStrategy::Clamp,
+                        strategy => strategy,
+                    };
+                    match strategy {
+                        WrapStrategy::Wrap => offset += self.wrap_modulus,
+                        WrapStrategy::Clamp => offset += from - to,
+                        WrapStrategy::Split => {
+                            sessions.push(Vec::new());
+                            offset = 0;
+                        }
+                        WrapStrategy::Error => {
+                            return Err(ParseError::Backwards {
+                                file: sample.name,
+                                line,
+                                from,
+                                to,
+                            })
+                        }
+                    }
+                    events.push(WrapEvent {
+                        file: sample.name.clone(),
+                        line,
+                        from,
+                        to,
+                        strategy,
+                    });
+                }
+
+                sessions.last_mut().unwrap().push(Keystroke {
+                    timestamp: keystroke.timestamp + offset,
+                    ..*keystroke
+                });
+            }
+
+            for event in &events[found..] {
+                println!(
+                    "{}: timestamp goes backwards from {} to {}{}, handled with '{:?}'",
+                    event.file,
+                    event.from,
+                    event.to,
+                    at_line(event.line),
+                    event.strategy
+                );
+            }
+
+            if sessions.len() == 1 {
+                out.push(Sample {
+                    keystrokes: sessions.pop().unwrap_or_default(),
+                    ..sample
+                });
+            } else {
+                let path = Path::new(&sample.name);
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let extension = path
+                    .extension()
+                    .map(|extension| format!(".{}", extension.to_string_lossy()))
+                    .unwrap_or_default();
+                out.extend(
+                    sessions
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, keystrokes)| Sample {
+                            name: format!("{stem}-{}{extension}", i + 1),
+                            keystrokes,
+                        }),
+                );
+            }
+        }
+
+        Ok((out, events))
+    }
+}
+
+/// Step of a free-text simulation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Task {
+    /// Wait for the given seconds
+    Wait(f64),
+    /// Press and release a key
+    Key(Key),
+    /// Press a key and keep it held down (Shift)
+    Press(Key),
+    /// Release a held down key
+    Release(Key),
+}
+
+impl Task {
+    /// Key and the events injected for it, `None` for waits.
+    pub const fn key_events(self) -> Option<(Key, &'static [Direction])> {
+        match self {
+            Self::Wait(_) => None,
+            Self::Key(key) => Some((key, &[Direction::Down, Direction::Up])),
+            Self::Press(key) => Some((key, &[Direction::Down])),
+            Self::Release(key) => Some((key, &[Direction::Up])),
+        }
+    }
+}
+
+/// How Shift is simulated for shifted characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShiftMode {
+    /// Hold Shift down while pressing the base key, like the user did
+    Faithful,
+    /// Only press the base key (e.g. 'a' for 'A'), without Shift
+    Fold,
+}
+
+/// How the key codes of a sample are encoded, the dataset contains both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEncoding {
+    /// JavaScript `keyCode` of keydown events: letters are upper case, Shift (16) is a key of its own
+    KeyCode,
+    /// Character code of keypress events: ASCII, shifted characters have codes of their own
+    CharCode,
+}
+
+/// Key that is simulated for a key code of the dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mapped {
+    /// Key, and if Shift is held down while pressing it
+    Key { key: Key, shift: bool },
+    /// Shift, held down for the next key
+    Shift,
+    /// No key is simulated
+    Unmapped,
+}
+
+/// Shifted characters of the US layout and their base key.
+const SHIFTED: [(char, char); 21] = [
+    ('!', '1'),
+    ('@', '2'),
+    ('#', '3'),
+    ('$', '4'),
+    ('%', '5'),
+    ('^', '6'),
+    ('&', '7'),
+    ('*', '8'),
+    ('(', '9'),
+    (')', '0'),
+    ('_', '-'),
+    ('+', '='),
+    ('{', '['),
+    ('}', ']'),
+    ('|', '\\'),
+    (':', ';'),
+    ('"', '\''),
+    ('<', ','),
+    ('>', '.'),
+    ('?', '/'),
+    ('~', '`'),
+];
+
+impl KeyEncoding {
+    /// Guess the encoding of a sample, lower case letters only exist as character codes.
+    pub fn detect(keystrokes: &[Keystroke]) -> Self {
+        let count = |codes: std::ops::RangeInclusive<u8>| {
+            keystrokes
+                .iter()
+                .filter(|keystroke| codes.contains(&keystroke.code))
+                .count()
+        };
+
+        if count(b'a'..=b'z') > count(b'A'..=b'Z') {
+            Self::CharCode
+        } else {
+            Self::KeyCode
+        }
+    }
+
+    /// Key simulated for `code`.
+    pub fn map(self, code: u8) -> Mapped {
+        let key = |key| Mapped::Key { key, shift: false };
+        let layout = |c| key(Key::Layout(c));
+
+        match (self, code) {
+            (_, 8) => key(Key::Backspace),
+            (_, 9) => key(Key::Tab),
+            (_, 13) => key(Key::Return),
+            (_, 32) => key(Key::Space),
+            (Self::CharCode, 127) => key(Key::Delete),
+            (Self::CharCode, code) => {
+                let c = char::from(code);
+                if c.is_ascii_uppercase() {
+                    return Mapped::Key {
+                        key: Key::Layout(c.to_ascii_lowercase()),
+                        shift: true,
+                    };
+                }
+                if c.is_ascii_lowercase() || c.is_ascii_digit() || "-=[]\\;',./`".contains(c) {
+                    return layout(c);
+                }
+                SHIFTED.iter().find(|(shifted, _)| *shifted == c).map_or(
+                    Mapped::Unmapped,
+                    |(_, base)| Mapped::Key {
+                        key: Key::Layout(*base),
+                        shift: true,
+                    },
+                )
+            }
+            (Self::KeyCode, code) => match code {
+                16 => Mapped::Shift,
+                33 => key(Key::PageUp),
+                34 => key(Key::PageDown),
+                35 => key(Key::End),
+                36 => key(Key::Home),
+                37 => key(Key::LeftArrow),
+                38 => key(Key::UpArrow),
+                39 => key(Key::RightArrow),
+                40 => key(Key::DownArrow),
+                46 => key(Key::Delete),
+                b'0'..=b'9' => layout(char::from(code)),
+                b'A'..=b'Z' => layout(char::from(code.to_ascii_lowercase())),
+                // numpad
+                96..=105 => layout(char::from(code - 48)),
+                106 => layout('*'),
+                107 => layout('+'),
+                // numpad and main keyboard
+                109 | 189 => layout('-'),
+                110 | 190 => layout('.'),
+                111 | 191 => layout('/'),
+                186 => layout(';'),
+                187 => layout('='),
+                188 => layout(','),
+                192 => layout('`'),
+                219 => layout('['),
+                220 => layout('\\'),
+                221 => layout(']'),
+                222 => layout('\''),
+                // Control, Alt, CapsLock, Escape (download key), ... would change the state of the browser
+                _ => Mapped::Unmapped,
+            },
+        }
+    }
+
+    /// Key code of `key` (`KeyCode` encoding), the inverse of [`KeyEncoding::map`].
+    /// Shifted characters have the code of their base key, like in the browser.
+    pub fn key_code(key: Key) -> Option<u8> {
+        let code = match key {
+            Key::Backspace => 8,
+            Key::Tab => 9,
+            Key::Return => 13,
+            Key::Shift => 16,
+            Key::Control => 17,
+            Key::Alt => 18,
+            Key::CapsLock => 20,
+            Key::Escape => 27,
+            Key::Space => 32,
+            Key::PageUp => 33,
+            Key::PageDown => 34,
+            Key::End => 35,
+            Key::Home => 36,
+            Key::LeftArrow => 37,
+            Key::UpArrow => 38,
+            Key::RightArrow => 39,
+            Key::DownArrow => 40,
+            Key::Delete => 46,
+            Key::Layout(c) => {
+                let base = SHIFTED
+                    .iter()
+                    .find(|(shifted, _)| *shifted == c)
+                    .map_or(c, |(_, base)| *base);
+                match base {
+                    ' ' => 32,
+                    'a'..='z' | 'A'..='Z' | '0'..='9' => {
+                        u8::try_from(base.to_ascii_uppercase()).ok()?
+                    }
+                    ';' => 186,
+                    '=' => 187,
+                    ',' => 188,
+                    '-' => 189,
+                    '.' => 190,
+                    '/' => 191,
+                    '`' => 192,
+                    '[' => 219,
+                    '\\' => 220,
+                    ']' => 221,
+                    '\'' => 222,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(code)
+    }
+}
+
+/// Tasks simulating the keystrokes of a sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskList {
+    pub tasks: Vec<Task>,
+    /// Seconds each keystroke is held down, `None` if no key is simulated for it
+    pub holds: Vec<Option<f64>>,
+    /// How often each key code, that is not simulated, was found
+    pub unmapped: BTreeMap<u8, usize>,
+}
+
+/// Key events of a sample in microseconds since its first keystroke, before they are sorted.
+#[derive(Debug, Default)]
+struct Timeline {
+    events: Vec<(u64, Task)>,
+    /// Keys, whose release is already scheduled: key, keystroke, time it was pressed, index of the release
+    releases: Vec<(Key, Option<usize>, u64, usize)>,
+    holds: Vec<Option<f64>>,
+}
+
+impl Timeline {
+    /// Press `key` at `time` and release it `hold` microseconds later, returns the time it is released.
+    fn press(&mut self, time: u64, key: Key, hold: u64, keystroke: Option<usize>) -> u64 {
+        self.cut_hold(key, time);
+        if hold == 0 {
+            self.events.push((time, Task::Key(key)));
+        } else {
+            self.events.push((time, Task::Press(key)));
+            self.release(time, key, time + hold, keystroke);
+        }
+        if let Some(keystroke) = keystroke {
+            self.holds[keystroke] = Some(seconds(hold));
+        }
+        time + hold
+    }
+
+    /// Release `key` (pressed at `pressed`) at `time`.
+    fn release(&mut self, pressed: u64, key: Key, time: u64, keystroke: Option<usize>) {
+        self.releases
+            .push((key, keystroke, pressed, self.events.len()));
+        self.events.push((time, Task::Release(key)));
+        if let Some(keystroke) = keystroke {
+            self.holds[keystroke] = Some(seconds(time - pressed));
+        }
+    }
+
+    /// A key can not be pressed again while it is still held down,
+    /// so its previous release is moved to `time`, if it is later.
+    fn cut_hold(&mut self, key: Key, time: u64) {
+        let Some(position) = self.releases.iter().position(|(k, ..)| *k == key) else {
+            return;
+        };
+        let (_, keystroke, pressed, index) = self.releases.remove(position);
+        if self.events[index].0 > time {
+            self.events[index].0 = time;
+            if let Some(keystroke) = keystroke {
+                self.holds[keystroke] = Some(seconds(time - pressed));
+            }
+        }
+    }
+}
+
+/// Convert microseconds to seconds.
+#[allow(clippy::cast_precision_loss)]
+fn seconds(micros: u64) -> f64 {
+    micros as f64 / 1_000_000.0
+}
+
+/// Convert the keystrokes of a sample to the tasks simulating them.
+/// Timestamps are expected to be increasing, see [`WrapOptions::apply`].
+///
+/// Keys are held down for their recorded hold time, or the time drawn from `holds` if only
+/// the keydown was recorded. If the next key is pressed before that,
+/// both are held down at the same time (rollover).
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn create_task_list(
+    keystrokes: &[Keystroke],
+    shift_mode: ShiftMode,
+    holds: &mut HoldModel,
+) -> TaskList {
+    let mut timeline = Timeline {
+        holds: vec![None; keystrokes.len()],
+        ..Timeline::default()
+    };
+    let mut unmapped = BTreeMap::new();
+
+    let first = keystrokes
+        .first()
+        .map_or(0, |keystroke| keystroke.timestamp);
+    let last = keystrokes.last().map_or(0, |keystroke| keystroke.timestamp);
+
+    let encoding = KeyEncoding::detect(keystrokes);
+    println!(
+        "Task Queue takes: {:.2}min ({encoding:?})",
+        ((last - first) as f32 / 1000.0) / 60.0
+    );
+
+    // Shift is held down from its own key code (16) until the next key is released
+    let faithful = shift_mode == ShiftMode::Faithful;
+    let mut shift_held = None;
+    // a recorded Shift is held down until its own keyup
+    let mut shift_recorded = 0;
+    let mut end = 0;
+
+    for (k, keystroke) in keystrokes.iter().enumerate() {
+        // milliseconds to microseconds
+        let time = (keystroke.timestamp - first) * 1000;
+        end = end.max(time);
+
+        match encoding.map(keystroke.code) {
+            Mapped::Shift if faithful && shift_held.is_none() => {
+                timeline.cut_hold(Key::Shift, time);
+                timeline.events.push((time, Task::Press(Key::Shift)));
+                if let Some(hold) = keystroke.hold {
+                    shift_recorded = time + hold * 1000;
+                    timeline.release(time, Key::Shift, shift_recorded, Some(k));
+                    end = end.max(shift_recorded);
+                } else {
+                    shift_held = Some((time, Some(k)));
+                }
+            }
+            Mapped::Shift => {}
+            Mapped::Key { key, shift } => {
+                let recorded = time < shift_recorded;
+                if shift && faithful && shift_held.is_none() && !recorded {
+                    timeline.cut_hold(Key::Shift, time);
+                    timeline.events.push((time, Task::Press(Key::Shift)));
+                    shift_held = Some((time, None));
+                } else if shift_held.is_none() && !recorded {
+                    // a Shift still held for a previous key (rollover) would shift this one
+                    timeline.cut_hold(Key::Shift, time);
+                }
+
+                let hold = keystroke.hold.map_or_else(
+                    || (holds.hold(key) * 1_000_000.0).round() as u64,
+                    |hold| hold * 1000,
+                );
+                let released = timeline.press(time, key, hold, Some(k));
+                end = end.max(released);
+
+                if let Some((pressed, keystroke)) = shift_held.take() {
+                    timeline.release(pressed, Key::Shift, released, keystroke);
+                }
+            }
+            Mapped::Unmapped => *unmapped.entry(keystroke.code).or_default() += 1,
+        }
+    }
+
+    if let Some((pressed, keystroke)) = shift_held {
+        timeline.release(pressed, Key::Shift, end, keystroke);
+    }
+
+    // releases can be later than the next press, the sort is stable
+    let Timeline {
+        mut events, holds, ..
+    } = timeline;
+    events.sort_by_key(|(time, _)| *time);
+
+    let mut tasks = Vec::with_capacity(events.len() * 2);
+    let mut now = 0;
+    for (time, task) in events {
+        if time != now {
+            tasks.push(Task::Wait(seconds(time - now)));
+            now = time;
+        }
+        tasks.push(task);
+    }
+
+    TaskList {
+        tasks,
+        holds,
+        unmapped,
+    }
+}
+
+/// Key that is really simulated for `key`.
+/// On macos, only 'a' is simulated.
+#[cfg(target_os = "macos")]
+pub const fn simulated_key(_: Key) -> Key {
+    Key::Layout('a')
+}
+
+/// Key that is really simulated for `key`.
+/// On macos, only 'a' is simulated.
+#[cfg(not(target_os = "macos"))]
+pub const fn simulated_key(key: Key) -> Key {
+    key
+}
+
+#[test]
+fn test_parse_sample() {
+    let keystrokes = parse_sample("s01", "99990\n104\n10\n105\n10\n8\n\n").unwrap();
+    assert_eq!(keystrokes.len(), 3);
+    let sample = Sample {
+        name: "s01.txt".into(),
+        keystrokes,
+    };
+    let options = |wrap_strategy| WrapOptions {
+        wrap_modulus: 100_000,
+        wrap_strategy,
+        max_wrap_gap: 10_000,
+    };
+
+    let (samples, events) = options(WrapStrategy::Wrap)
+        .apply(vec![sample.clone()])
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].line, Some(3));
+    assert_eq!(
+        create_task_list(
+            &samples[0].keystrokes,
+            ShiftMode::Faithful,
+            &mut HoldModel::none()
+        )
+        .tasks,
+        [
+            Task::Key(Key::Layout('h')),
+            Task::Wait(0.02),
+            Task::Key(Key::Layout('i')),
+            Task::Key(Key::Backspace),
+        ]
+    );
+
+    let (samples, _) = options(WrapStrategy::Clamp)
+        .apply(vec![sample.clone()])
+        .unwrap();
+    let list = create_task_list(
+        &samples[0].keystrokes,
+        ShiftMode::Faithful,
+        &mut HoldModel::none(),
+    );
+    assert!(!list.tasks.contains(&Task::Wait(0.02)));
+
+    let (samples, _) = options(WrapStrategy::Split)
+        .apply(vec![sample.clone()])
+        .unwrap();
+    let names = samples.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["s01-1.txt", "s01-2.txt"]);
+
+    assert!(options(WrapStrategy::Error)
+        .apply(vec![sample.clone()])
+        .is_err());
+    let mismatch = WrapOptions {
+        wrap_modulus: 50_000,
+        ..options(WrapStrategy::Wrap)
+    };
+    assert!(mismatch.apply(vec![sample]).is_err());
+    // the timestamps around the wrap-around fit the modulus, a later one does not
+    let sample = Sample {
+        name: "s02.txt".into(),
+        keystrokes: parse_sample("s02", "45000\n104\n10\n105\n60000\n8\n").unwrap(),
+    };
+    let error = mismatch.apply(vec![sample]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Timestamp 60000 in s02.txt (line 5) does not fit a wrap-around at 50000"
+    );
+
+    // a small step back is out of order and clamped, not a wrap-around of almost 100s
+    let sample = Sample {
+        name: "s03.txt".into(),
+        keystrokes: parse_sample("s03", "99990\n104\n10\n105\n500\n106\n300\n8\n").unwrap(),
+    };
+    let (samples, events) = options(WrapStrategy::Wrap).apply(vec![sample]).unwrap();
+    let strategies = events.iter().map(|e| e.strategy).collect::<Vec<_>>();
+    assert_eq!(strategies, [WrapStrategy::Wrap, WrapStrategy::Clamp]);
+    let timestamps = samples[0].keystrokes.iter().map(|k| k.timestamp);
+    assert_eq!(
+        timestamps.collect::<Vec<_>>(),
+        [99_990, 100_010, 100_500, 100_500]
+    );
+
+    let error = parse_sample("s01", "10\n72\n20\n").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Timestamp without key code in s01 (line 3)"
+    );
+    let error = parse_sample("s01", "10\n72\n20\n300\n").unwrap_err();
+    assert_eq!(error.to_string(), "Invalid key code '300' in s01 (line 4)");
+    let error = parse_sample("s01", "10\n72\nx\n3\n").unwrap_err();
+    assert_eq!(error.to_string(), "Invalid timestamp 'x' in s01 (line 3)");
+}
+
+#[test]
+fn test_key_mapping() {
+    let keystrokes = |codes: &[u8]| {
+        codes
+            .iter()
+            .map(|&code| Keystroke {
+                timestamp: 0,
+                code,
+                hold: None,
+                line: None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let (a, shift) = (Key::Layout('a'), Key::Shift);
+    let tasks =
+        |keystrokes, shift_mode| create_task_list(keystrokes, shift_mode, &mut HoldModel::none());
+
+    // character codes: 'A', 'a', '?', DEL
+    let chars = keystrokes(&[65, 97, 97, 63, 127]);
+    assert_eq!(KeyEncoding::detect(&chars), KeyEncoding::CharCode);
+    let list = tasks(&chars, ShiftMode::Faithful);
+    assert_eq!(
+        list.tasks,
+        [
+            Task::Press(shift),
+            Task::Key(a),
+            Task::Release(shift),
+            Task::Key(a),
+            Task::Key(a),
+            Task::Press(shift),
+            Task::Key(Key::Layout('/')),
+            Task::Release(shift),
+            Task::Key(Key::Delete),
+        ]
+    );
+    assert!(list.unmapped.is_empty());
+    let list = tasks(&chars, ShiftMode::Fold);
+    assert!(!list.tasks.contains(&Task::Press(shift)));
+
+    // key codes: Shift, 'A', Return, Left, Control, ';', 0
+    let codes = keystrokes(&[16, 65, 13, 37, 17, 186, 0, 0]);
+    assert_eq!(KeyEncoding::detect(&codes), KeyEncoding::KeyCode);
+    let list = tasks(&codes, ShiftMode::Faithful);
+    assert_eq!(
+        list.tasks,
+        [
+            Task::Press(shift),
+            Task::Key(a),
+            Task::Release(shift),
+            Task::Key(Key::Return),
+            Task::Key(Key::LeftArrow),
+            Task::Key(Key::Layout(';')),
+        ]
+    );
+    assert_eq!(list.unmapped, BTreeMap::from([(0, 2), (17, 1)]));
+    assert_eq!(list.holds[4], None);
+
+    // named keys of other datasets are converted to key codes
+    for code in [8, 13, 16, 37, 48, 65, 90, 186, 191, 222] {
+        let key = match KeyEncoding::KeyCode.map(code) {
+            Mapped::Key { key, .. } => key,
+            Mapped::Shift => shift,
+            Mapped::Unmapped => unreachable!(),
+        };
+        assert_eq!(KeyEncoding::key_code(key), Some(code));
+    }
+    assert_eq!(KeyEncoding::key_code(Key::Layout('?')), Some(191));
+    assert_eq!(KeyEncoding::key_code(Key::Layout('é')), None);
+}
+
+#[test]
+fn test_hold_synthesis() {
+    let keystrokes =
+        [(0, b'a'), (50, b'B'), (80, b'a'), (100, b'a')].map(|(timestamp, code)| Keystroke {
+            timestamp,
+            code,
+            hold: None,
+            line: None,
+        });
+    let mut holds = crate::hold::HoldOptions {
+        hold: Some(crate::distribution::Distribution::Constant(0.1)),
+        hold_fit: None,
+        hold_seed: Some(1),
+    }
+    .model()
+    .unwrap();
+
+    // 'a' is still held down when 'B' is pressed (rollover), and released early,
+    // because it is pressed again, Shift is released before the next unshifted 'a'
+    // ("aBaa", not "aBAA")
+    let (a, b, shift) = (Key::Layout('a'), Key::Layout('b'), Key::Shift);
+    let list = create_task_list(&keystrokes, ShiftMode::Faithful, &mut holds);
+    assert_eq!(
+        list.tasks,
+        [
+            Task::Press(a),
+            Task::Wait(0.05),
+            Task::Press(shift),
+            Task::Press(b),
+            Task::Wait(0.03),
+            Task::Release(a),
+            Task::Release(shift),
+            Task::Press(a),
+            Task::Wait(0.02),
+            Task::Release(a),
+            Task::Press(a),
+            Task::Wait(0.05),
+            Task::Release(b),
+            Task::Wait(0.05),
+            Task::Release(a),
+        ]
+    );
+    assert_eq!(list.holds, [Some(0.08), Some(0.1), Some(0.02), Some(0.1)]);
+}
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use enigo::Key;
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::json;
+
+use crate::{distribution::Distribution, pw_timer, Error};
+
+/// Command line options for synthesizing how long free-text keys are held down.
+#[derive(Debug, Clone, clap::Args)]
+pub struct HoldOptions {
+    /// Specifies how many seconds keys are held down, either a constant ('0.1') or a distribution
+    /// ('uniform:<min>:<max>', 'exp:<mean>', 'normal:<mean>:<std>', 'file:<path>'), used for keys without a recorded keyup,
+    /// which are only tapped if not given
+    #[arg(long, conflicts_with = "hold_fit")]
+    pub hold: Option<Distribution>,
+
+    /// Specifies a CSV-File of the password dataset, hold times are fitted per key from its 'H.*' columns
+    #[arg(long)]
+    pub hold_fit: Option<PathBuf>,
+
+    /// Specifies the seed hold times are drawn with (random if not given)
+    #[arg(long)]
+    pub hold_seed: Option<u64>,
+}
+
+impl HoldOptions {
+    /// Build the hold-time model selected on the command line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the distribution can not be used for hold times,
+    /// or the password dataset could not be read.
+    pub fn model(&self) -> Result<HoldModel, Box<dyn std::error::Error>> {
+        let seed = self.hold_seed.unwrap_or_else(rand::random);
+
+        let (default, per_key) = match (&self.hold, &self.hold_fit) {
+            (Some(Distribution::Sweep { .. }), _) => {
+                return Err(Box::new(Error(
+                    "A sweep can not be used for hold times".to_owned(),
+                )));
+            }
+            (Some(distribution), _) => (Some(distribution.clone()), Vec::new()),
+            (None, Some(path)) => {
+                let (default, per_key) = fit(path)?;
+                (Some(default), per_key)
+            }
+            (None, None) => (None, Vec::new()),
+        };
+
+        if let Some(default) = &default {
+            println!(
+                "Hold times: {default} ({} keys fitted, seed {seed})",
+                per_key.len()
+            );
+        }
+
+        Ok(HoldModel {
+            default,
+            per_key,
+            rng: StdRng::seed_from_u64(seed),
+            index: 0,
+            seed,
+            fitted: self.hold_fit.clone(),
+        })
+    }
+}
+
+/// Distribution over all keys and per key.
+type Fitted = (Distribution, Vec<(Key, Distribution)>);
+
+/// Fit a normal distribution for every key of the password dataset,
+/// and one over all keys for keys that are not part of the password.
+fn fit(path: &Path) -> Result<Fitted, Box<dyn std::error::Error>> {
+    // Shift.r and r are the same key
+    let mut samples: Vec<(Key, Vec<f64>)> = Vec::new();
+    for (key, holds) in pw_timer::read_hold_times(path)? {
+        match samples.iter_mut().find(|(k, _)| *k == key.key) {
+            Some((_, all)) => all.extend(holds),
+            None => samples.push((key.key, holds)),
+        }
+    }
+
+    let all = samples
+        .iter()
+        .flat_map(|(_, holds)| holds.iter().copied())
+        .collect::<Vec<_>>();
+    let default =
+        normal(&all).ok_or_else(|| Error(format!("No hold times found in {}", path.display())))?;
+    let per_key = samples
+        .into_iter()
+        .filter_map(|(key, holds)| Some((key, normal(&holds)?)))
+        .collect();
+
+    Ok((default, per_key))
+}
+
+/// Normal distribution with the mean and standard deviation of `values`.
+#[allow(clippy::cast_precision_loss)]
+fn normal(values: &[f64]) -> Option<Distribution> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    Some(Distribution::Normal {
+        mean,
+        std: variance.sqrt(),
+    })
+}
+
+/// Draws how long each simulated key is held down.
+#[derive(Debug)]
+pub struct HoldModel {
+    /// Distribution for keys without a fitted distribution, `None` if keys are only tapped
+    default: Option<Distribution>,
+    per_key: Vec<(Key, Distribution)>,
+    rng: StdRng,
+    /// Number of hold times drawn, to step through 'file:' distributions
+    index: usize,
+    seed: u64,
+    fitted: Option<PathBuf>,
+}
+
+impl HoldModel {
+    /// Keys are only tapped (pressed and released immediately).
+    pub fn none() -> Self {
+        Self {
+            default: None,
+            per_key: Vec::new(),
+            rng: StdRng::seed_from_u64(0),
+            index: 0,
+            seed: 0,
+            fitted: None,
+        }
+    }
+
+    /// Seconds `key` is held down, 0 if it is only tapped.
+    pub fn hold(&mut self, key: Key) -> f64 {
+        let distribution = self
+            .per_key
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, distribution)| distribution)
+            .or(self.default.as_ref());
+
+        distribution.map_or(0.0, |distribution| {
+            self.index += 1;
+            distribution.draw(&mut self.rng, self.index - 1, usize::MAX)
+        })
+    }
+
+    /// Description of the model, recorded with the run.
+    pub fn info(&self) -> serde_json::Value {
+        let Some(default) = &self.default else {
+            return serde_json::Value::Null;
+        };
+
+        let per_key = self
+            .per_key
+            .iter()
+            .map(|(key, distribution)| (format!("{key:?}"), distribution.to_string().into()))
+            .collect::<serde_json::Map<_, _>>();
+        json!({
+            "distribution": default.to_string(),
+            "fitted": self.fitted,
+            "per_key": per_key,
+            "seed": self.seed,
+        })
+    }
+}
@@ -0,0 +1,119 @@
+use std::{fmt::Display, str::FromStr, sync::Mutex, thread};
+
+use rdev::{grab, Event, EventType, Key};
+
+use crate::Error;
+
+/// Keys that can be used as global hotkeys, they are never injected by a simulation.
+/// Registered hotkeys are swallowed, so they never reach the browser.
+const HOTKEYS: [(&str, Key); 15] = [
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("Pause", Key::Pause),
+    ("ScrollLock", Key::ScrollLock),
+    ("PrintScreen", Key::PrintScreen),
+];
+
+/// A global hotkey, e.g. `Pause` or `ScrollLock`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HotKey(pub Key);
+
+impl FromStr for HotKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HOTKEYS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, key)| Self(*key))
+            .ok_or_else(|| {
+                let names = HOTKEYS.map(|(name, _)| name);
+                format!(
+                    "Invalid hotkey '{s}', expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl Display for HotKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = HOTKEYS
+            .iter()
+            .find(|(_, key)| *key == self.0)
+            .map_or("?", |(name, _)| name);
+        write!(f, "{name}")
+    }
+}
+
+/// Function called when its hotkey is pressed.
+type Handler = (HotKey, fn());
+
+/// Handlers of all registered hotkeys, shared with the listening thread.
+static HANDLERS: Mutex<Vec<Handler>> = Mutex::new(Vec::new());
+
+/// Call `handler` whenever `hotkey` is pressed.
+/// All hotkeys are grabbed by one background thread, started with the first registration,
+/// the hotkey events are not passed on to other applications (e.g. the browser and its webapp).
+///
+/// # Errors
+///
+/// Returns an error, if `hotkey` is already used for something else.
+pub fn register(hotkey: HotKey, handler: fn()) -> Result<(), Box<dyn std::error::Error>> {
+    let mut handlers = HANDLERS.lock().unwrap();
+    if handlers.iter().any(|(registered, _)| *registered == hotkey) {
+        return Err(Box::new(Error(format!(
+            "Hotkey {hotkey} is used more than once"
+        ))));
+    }
+
+    if handlers.is_empty() {
+        let callback = |event: Event| {
+            let (EventType::KeyPress(key) | EventType::KeyRelease(key)) = event.event_type else {
+                return Some(event);
+            };
+            let handlers = HANDLERS.lock().unwrap();
+            let mut registered = handlers
+                .iter()
+                .filter(|(hotkey, _)| hotkey.0 == key)
+                .peekable();
+            if registered.peek().is_none() {
+                return Some(event);
+            }
+            if matches!(event.event_type, EventType::KeyPress(_)) {
+                registered.for_each(|(_, handler)| handler());
+            }
+            // swallow both the press and the release of the hotkey
+            None
+        };
+
+        thread::spawn(move || {
+            // This will block.
+            if let Err(error) = grab(callback) {
+                println!("Error: {error:?}");
+            }
+        });
+    }
+
+    handlers.push((hotkey, handler));
+    Ok(())
+}
+
+#[test]
+fn test_hotkey_names() {
+    assert_eq!("f12".parse::<HotKey>().unwrap(), HotKey(Key::F12));
+    assert_eq!("Pause".parse::<HotKey>().unwrap().to_string(), "Pause");
+    assert!("a".parse::<HotKey>().is_err());
+    // End is typed by free-text datasets
+    assert!("End".parse::<HotKey>().is_err());
+}
@@ -1,17 +1,51 @@
 #![warn(clippy::pedantic)]
 
+mod abort;
+mod calibration;
+mod clock;
+mod dataset;
 mod delay;
+mod distribution;
+mod event_log;
+mod filter;
 mod free_text;
+mod free_text_data;
+mod hold;
+mod hotkey;
 mod live;
+mod output;
+mod pause;
 mod pw_timer;
 mod raw_input;
+mod realtime;
+mod run_info;
+mod scheduler;
+mod shard;
+mod stats;
 mod timer_samples;
 
+use abort::AbortOptions;
+use calibration::CompensationOptions;
 use clap::{Parser, Subcommand};
+use clock::ClockOptions;
+use dataset::DatasetFormat;
+use delay::DelayStrategy;
+use distribution::Distribution;
 use enigo::Key;
+use filter::RowFilter;
+use free_text_data::{ShiftMode, WrapOptions};
+use hold::HoldOptions;
+use output::OverwriteOptions;
+use pause::PauseOptions;
+use pw_timer::{ModifierOptions, TimingModel, TimingOptions};
+use realtime::RealtimeOptions;
+use shard::ShardOptions;
+use std::path::PathBuf;
+use timer_samples::{InputKind, PhaseSweep};
 
 pub const DOWNLOAD_KEY: Key = Key::Escape; // no input character
 
+#[allow(clippy::too_many_lines)]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line args
     let args = Args::parse();
@@ -25,74 +59,236 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             delay,
             inputs,
             extended,
+            strategy,
+            clock,
+            event_log,
+            realtime,
+            overwrite,
         } => {
-            raw_input::capture_raw_input(output.as_str(), simulate, wait, delay, inputs, extended)?;
+            overwrite.check(&output)?;
+            overwrite.check_all(&event_log)?;
+
+            raw_input::capture_raw_input(
+                output.as_str(),
+                simulate,
+                wait,
+                delay,
+                inputs,
+                extended,
+                strategy,
+                &clock.create(),
+                event_log.as_deref(),
+                realtime,
+            )?;
         }
 
         // Simulate typing passwords
         Commands::Password {
             input,
-            mut output,
+            format,
+            output,
             sleep,
             download,
             warmup,
-            mut skip,
-            mut count,
-            part,
+            skip,
+            count,
+            shard,
+            filter,
+            strategy,
+            clock,
+            event_log,
+            realtime,
+            compensation,
+            timing,
+            modifier,
+            resume,
+            overwrite,
+            abort,
+            pause,
         } => {
-            if let Some(part) = part {
-                match part {
-                    // [0..5300]
-                    1 => {
-                        skip = 0;
-                        count = 5_300;
-                        output = "./password_data_rs.csv (1)".into();
-                    }
-                    // [5300.. 10400]
-                    2 => {
-                        skip = 5_300;
-                        count = 5_100;
-                        output = "./password_data_rs.csv (2)".into();
-                    }
-                    // [10400..15000]
-                    3 => {
-                        skip = 10_400;
-                        count = 4_600;
-                        output = "./password_data_rs.csv (3)".into();
-                    }
-                    // [15000..20400]
-                    4 => {
-                        skip = 15_000;
-                        count = 5_400;
-                        output = "./password_data_rs.csv (4)".into();
-                    }
-                    _ => unreachable!(),
-                }
+            // name outputs after the shard they contain
+            let output = shard.output_path(output);
+            let event_log = event_log.map(|event_log| shard.output_path(event_log));
+
+            // a resumed run appends to its previous results, a check writes no results
+            if !resume && timing.timing_model != TimingModel::Check {
+                overwrite.check(&output)?;
+                overwrite.check_all(&event_log)?;
             }
+            abort.install()?;
+            pause.install()?;
 
             pw_timer::pw_simulation(
                 input.as_str(),
-                output.as_str(),
+                format,
+                output,
                 sleep,
                 download,
                 warmup,
                 skip,
                 count,
+                shard,
+                &filter,
+                strategy,
+                &clock.create(),
+                event_log.as_deref(),
+                realtime,
+                compensation.load()?.as_ref(),
+                timing,
+                modifier,
+                resume,
             )?;
         }
 
         // Take timestamp probes every delay(sec)
-        Commands::Timer { iterations, delay } => {
-            timer_samples::browser_timer_sampler(iterations, delay);
+        Commands::Timer {
+            iterations,
+            delay,
+            seed,
+            kind,
+            hold,
+            output,
+            strategy,
+            clock,
+            event_log,
+            realtime,
+            sweep,
+            overwrite,
+        } => {
+            overwrite.check_all(&output)?;
+            overwrite.check_all(&event_log)?;
+
+            if let Some(window) = sweep.window {
+                timer_samples::phase_sweep_sampler(
+                    window,
+                    sweep,
+                    &delay,
+                    kind,
+                    hold,
+                    output.as_deref(),
+                    strategy,
+                    &clock.create(),
+                    event_log.as_deref(),
+                    realtime,
+                )?;
+            } else {
+                timer_samples::browser_timer_sampler(
+                    iterations,
+                    &delay,
+                    seed,
+                    kind,
+                    hold,
+                    output.as_deref(),
+                    strategy,
+                    &clock.create(),
+                    event_log.as_deref(),
+                    realtime,
+                )?;
+            }
         }
 
         // Simulate typing random/free text
-        Commands::FreeText { input_desc, warmup } => {
-            free_text::free_text_simulation(input_desc, warmup)?;
+        Commands::FreeText {
+            input_desc,
+            format,
+            output_dir,
+            warmup,
+            strategy,
+            clock,
+            event_log,
+            realtime,
+            compensation,
+            overwrite,
+            abort,
+            pause,
+            wrap,
+            shift_mode,
+            hold,
+        } => {
+            // every run gets its own directory, unless one is given
+            let output_dir = output_dir.map_or_else(
+                || output::timestamped_dir("./free-text-output"),
+                PathBuf::from,
+            );
+            overwrite.check(&output_dir)?;
+            overwrite.check_all(&event_log)?;
+            let holds = hold.model()?;
+            abort.install()?;
+            pause.install()?;
+
+            free_text::free_text_simulation(
+                input_desc,
+                format,
+                output_dir,
+                warmup,
+                strategy,
+                &clock.create(),
+                event_log.as_deref(),
+                realtime,
+                wrap,
+                shift_mode,
+                holds,
+                compensation.load()?.as_ref(),
+            )?;
+        }
+
+        // Measure latency between injecting and observing key events
+        Commands::Calibrate {
+            count,
+            keys,
+            hold,
+            delay,
+            output,
+            baseline,
+            strategy,
+            clock,
+            realtime,
+            overwrite,
+        } => {
+            overwrite.check(&output)?;
+
+            calibration::calibrate(
+                output.as_str(),
+                count,
+                keys.as_str(),
+                hold,
+                delay,
+                baseline.as_deref(),
+                strategy,
+                &clock.create(),
+                realtime,
+            )?;
         }
 
         // Simulate Freetext typing to watch live
-        Commands::Live { input } => live::live_simulation(input.as_str())?,
+        Commands::Live {
+            input,
+            format,
+            strategy,
+            clock,
+            event_log,
+            realtime,
+            abort,
+            pause,
+            wrap,
+            shift_mode,
+            hold,
+        } => {
+            let holds = hold.model()?;
+            abort.install()?;
+            pause.install()?;
+            live::live_simulation(
+                input.as_str(),
+                format,
+                strategy,
+                &clock.create(),
+                event_log.as_deref(),
+                realtime,
+                wrap,
+                shift_mode,
+                holds,
+            )?;
+        }
     };
 
     Ok(())
@@ -120,18 +316,65 @@ pub enum Commands {
         #[arg(short, long, default_value_t = 1010)]
         iterations: usize,
 
-        /// Specifies how many seconds to wait between each input event
+        /// Specifies how many seconds to wait between each input event, either a constant ('0.1')
+        /// or a distribution ('uniform:<min>:<max>', 'exp:<mean>', 'sweep:<from>:<to>', 'file:<path>')
         #[clap(about)]
-        #[arg(short, long, default_value_t = 0.1)]
-        delay: f64,
+        #[arg(short, long, default_value = "0.1")]
+        delay: Distribution,
+
+        /// Specifies the seed random delays are drawn with (random if not given)
+        #[clap(about)]
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Specifies which kind of input event should be simulated
+        #[clap(about)]
+        #[arg(short, long, value_enum, default_value_t = InputKind::KeyDown)]
+        kind: InputKind,
+
+        /// Specifies how many seconds a key/mouse button is held before it is released
+        /// ('mouse-down-up' and 'key-down-up' only, added on top of the delay)
+        #[clap(about)]
+        #[arg(long, default_value_t = 0.05)]
+        hold: f64,
+
+        /// Specifies CSV file to write the requested and actual delay of each input event to
+        #[clap(about)]
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Specifies CSV (or '.jsonl') file to write per-event injection timings to
+        #[clap(about)]
+        #[arg(long)]
+        event_log: Option<String>,
+
+        #[command(flatten)]
+        strategy: DelayStrategy,
+
+        #[command(flatten)]
+        clock: ClockOptions,
+
+        #[command(flatten)]
+        realtime: RealtimeOptions,
+
+        #[command(flatten)]
+        sweep: PhaseSweep,
+
+        #[command(flatten)]
+        overwrite: OverwriteOptions,
     },
     /// Simulates password input according to given dateset (input)
     Password {
-        /// Specifies CSV file to read input from
+        /// Specifies the dataset to read passwords from (a CMU CSV file, or see '--format')
         #[clap(about)]
         #[arg(short, long, default_value = "./DSL-StrongPasswordData.csv")]
         input: String,
 
+        /// Specifies the format of the dataset ('auto' detects it from the file extension)
+        #[clap(about)]
+        #[arg(long, value_enum, default_value_t = DatasetFormat::Auto)]
+        format: DatasetFormat,
+
         /// Specifies CSV file to write output to
         #[clap(about)]
         #[arg(short, long, default_value = "./password_data_rs.csv")]
@@ -152,21 +395,58 @@ pub enum Commands {
         #[arg(short, long, default_value_t = false)]
         warmup: bool,
 
-        /// Specifies how many passwords to skip before starting simulation. Valid values are (0 - 20400)
+        /// Specifies how many passwords to skip before starting simulation
         #[clap(about)]
-        #[arg(short, long, default_value_t = 0)]
+        #[arg(long, default_value_t = 0, conflicts_with = "shard")]
         skip: usize,
 
-        /// Specifies how many passwords should be simulated. Valid values are (0 - 20400)
+        /// Specifies how many passwords should be simulated (all remaining if not given)
         #[clap(about)]
-        #[arg(short, long, default_value_t = 20400)]
-        count: usize,
+        #[arg(short, long, conflicts_with = "shard")]
+        count: Option<usize>,
 
-        /// Shortcut to using 'skip' and 'count' args.
-        /// Valid values are, [1, 2, 3, 4].
-        #[clap(about, value_parser = validate_part_input)]
-        #[arg(short, long)]
-        part: Option<u8>,
+        #[command(flatten)]
+        shard: ShardOptions,
+
+        #[command(flatten)]
+        filter: RowFilter,
+
+        /// Specifies if an interrupted run should be continued, appending to its output
+        #[clap(about)]
+        #[arg(long, default_value_t = false)]
+        resume: bool,
+
+        /// Specifies CSV (or '.jsonl') file to write per-event injection timings to
+        #[clap(about)]
+        #[arg(long)]
+        event_log: Option<String>,
+
+        #[command(flatten)]
+        strategy: DelayStrategy,
+
+        #[command(flatten)]
+        clock: ClockOptions,
+
+        #[command(flatten)]
+        realtime: RealtimeOptions,
+
+        #[command(flatten)]
+        compensation: CompensationOptions,
+
+        #[command(flatten)]
+        timing: TimingOptions,
+
+        #[command(flatten)]
+        modifier: ModifierOptions,
+
+        #[command(flatten)]
+        overwrite: OverwriteOptions,
+
+        #[command(flatten)]
+        abort: AbortOptions,
+
+        #[command(flatten)]
+        pause: PauseOptions,
     },
 
     /// Captures user input (listening on Key `0`) and writes timestamps to output file.
@@ -200,27 +480,173 @@ pub enum Commands {
         #[clap(about)]
         #[arg(short, long, default_value_t = false)]
         extended: bool,
+
+        /// Specifies CSV (or '.jsonl') file to write per-event injection timings to
+        #[clap(about)]
+        #[arg(long)]
+        event_log: Option<String>,
+
+        #[command(flatten)]
+        strategy: DelayStrategy,
+
+        #[command(flatten)]
+        clock: ClockOptions,
+
+        #[command(flatten)]
+        realtime: RealtimeOptions,
+
+        #[command(flatten)]
+        overwrite: OverwriteOptions,
     },
 
     /// Simulates free-text input, based on the given input description ('./KEYSTROKE-SAMPLES-31-USERS/split_')
     FreeText {
-        /// Specifies JSON file listing all individual input files to read
+        /// Specifies JSON file listing all individual input files to read (or a dataset, see '--format')
         #[clap(about)]
         #[arg(short, long)]
         input_desc: String,
 
+        /// Specifies the format of the dataset ('auto' detects it from the file extension)
+        #[clap(about)]
+        #[arg(long, value_enum, default_value_t = DatasetFormat::Auto)]
+        format: DatasetFormat,
+
+        /// Specifies directory to write output to (a new directory in './free-text-output' if not given)
+        #[clap(about)]
+        #[arg(short, long)]
+        output_dir: Option<String>,
+
         /// Specifies if some dummy input events should be triggered before each text input
         #[clap(about)]
         #[arg(short, long, default_value_t = false)]
         warmup: bool,
+
+        /// Specifies CSV (or '.jsonl') file to write per-event injection timings to
+        #[clap(about)]
+        #[arg(long)]
+        event_log: Option<String>,
+
+        /// Specifies if shifted characters are typed with Shift held down, or folded to their base key
+        #[clap(about)]
+        #[arg(long, value_enum, default_value_t = ShiftMode::Faithful)]
+        shift_mode: ShiftMode,
+
+        #[command(flatten)]
+        strategy: DelayStrategy,
+
+        #[command(flatten)]
+        clock: ClockOptions,
+
+        #[command(flatten)]
+        realtime: RealtimeOptions,
+
+        #[command(flatten)]
+        compensation: CompensationOptions,
+
+        #[command(flatten)]
+        overwrite: OverwriteOptions,
+
+        #[command(flatten)]
+        abort: AbortOptions,
+
+        #[command(flatten)]
+        pause: PauseOptions,
+
+        #[command(flatten)]
+        wrap: WrapOptions,
+
+        #[command(flatten)]
+        hold: HoldOptions,
+    },
+
+    /// Measures the latency between injecting key events and the OS reporting them
+    Calibrate {
+        /// Specifies how many keys should be injected (each with a keydown and keyup)
+        #[clap(about)]
+        #[arg(short, long, default_value_t = 200)]
+        count: usize,
+
+        /// Specifies which keys (lowercase letters, digits or space) should be injected, in turn
+        #[clap(about)]
+        #[arg(short, long, default_value = "abcdefghijklmnopqrstuvwxyz")]
+        keys: String,
+
+        /// Specifies how many seconds each key should be held down
+        #[clap(about)]
+        #[arg(long, default_value_t = 0.05)]
+        hold: f64,
+
+        /// Specifies how many seconds to wait between each key
+        #[clap(about)]
+        #[arg(short, long, default_value_t = 0.1)]
+        delay: f64,
+
+        /// Specifies JSON file to write the calibration profile to
+        #[clap(about)]
+        #[arg(short, long, default_value = "./calibration.json")]
+        output: String,
+
+        /// Specifies an earlier calibration profile to compare the results against
+        #[clap(about)]
+        #[arg(short, long)]
+        baseline: Option<String>,
+
+        #[command(flatten)]
+        strategy: DelayStrategy,
+
+        #[command(flatten)]
+        clock: ClockOptions,
+
+        #[command(flatten)]
+        realtime: RealtimeOptions,
+
+        #[command(flatten)]
+        overwrite: OverwriteOptions,
     },
 
     /// Simulates free-text input, based on the given input description ('./KEYSTROKE-SAMPLES-31-USERS/split_')
     Live {
-        /// Specifies file with keystroke data
+        /// Specifies file with keystroke data (see '--format')
         #[clap(about)]
         #[arg(short, long)]
         input: String,
+
+        /// Specifies the format of the dataset ('auto' detects it from the file extension)
+        #[clap(about)]
+        #[arg(long, value_enum, default_value_t = DatasetFormat::Auto)]
+        format: DatasetFormat,
+
+        /// Specifies CSV (or '.jsonl') file to write per-event injection timings to,
+        /// the run info is written next to it (to a new directory in './live-output' if not given)
+        #[clap(about)]
+        #[arg(long)]
+        event_log: Option<String>,
+
+        /// Specifies if shifted characters are typed with Shift held down, or folded to their base key
+        #[clap(about)]
+        #[arg(long, value_enum, default_value_t = ShiftMode::Faithful)]
+        shift_mode: ShiftMode,
+
+        #[command(flatten)]
+        strategy: DelayStrategy,
+
+        #[command(flatten)]
+        clock: ClockOptions,
+
+        #[command(flatten)]
+        realtime: RealtimeOptions,
+
+        #[command(flatten)]
+        abort: AbortOptions,
+
+        #[command(flatten)]
+        pause: PauseOptions,
+
+        #[command(flatten)]
+        wrap: WrapOptions,
+
+        #[command(flatten)]
+        hold: HoldOptions,
     },
 }
 
@@ -234,16 +660,3 @@ impl std::fmt::Display for Error {
         write!(f, "{}", self.0)
     }
 }
-
-fn validate_part_input(p: &str) -> Result<u8, String> {
-    let part = p
-        .parse::<u8>()
-        .map_err(|_| "Invalid part argument. Has to be out of [1, 2, 3, 4]")?;
-    match part {
-        1 => Ok(1),
-        2 => Ok(2),
-        3 => Ok(3),
-        4 => Ok(4),
-        _ => Err("Invalid part argument. Has to be out of [1, 2, 3, 4]".into()),
-    }
-}
@@ -0,0 +1,107 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::Error;
+
+/// Command line option for overwriting the results of a previous run.
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct OverwriteOptions {
+    /// Specifies if existing results should be overwritten
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+}
+
+impl OverwriteOptions {
+    /// Check that writing to `path` does not destroy existing results.
+    /// Existing files and non-empty directories are only overwritten with `--force`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if `path` already holds results and `--force` is not given.
+    pub fn check<P: AsRef<Path>>(self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let exists = if path.is_dir() {
+            path.read_dir()?.next().is_some()
+        } else {
+            path.exists()
+        };
+
+        match (exists, self.force) {
+            (false, _) => Ok(()),
+            (true, true) => {
+                println!("Overwriting existing results in {}", path.display());
+                Ok(())
+            }
+            (true, false) => Err(Box::new(Error(format!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            )))),
+        }
+    }
+
+    /// Check every given path, see [`OverwriteOptions::check`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if any path already holds results and `--force` is not given.
+    pub fn check_all<P: AsRef<Path>>(
+        self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        paths.into_iter().try_for_each(|path| self.check(path))
+    }
+}
+
+/// Drop a last line without a newline from the file at `path`,
+/// it was only partially written (e.g. the run crashed while writing it).
+///
+/// # Errors
+///
+/// Returns an error, if the file could not be read or truncated.
+pub fn drop_partial_line<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let content = std::fs::read(path)?;
+    let complete = content
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |i| i + 1);
+    if complete < content.len() {
+        println!("Dropping partially written line of {}", path.display());
+        File::options()
+            .write(true)
+            .open(path)?
+            .set_len(complete as u64)?;
+    }
+    Ok(())
+}
+
+/// Directory for the results of a new run inside `base`, named after the current time,
+/// e.g. `./free-text-output/2023-06-14_15-30-00`.
+pub fn timestamped_dir<P: AsRef<Path>>(base: P) -> PathBuf {
+    let now = chrono::Local::now();
+    base.as_ref()
+        .join(now.format("%Y-%m-%d_%H-%M-%S").to_string())
+}
+
+#[test]
+fn test_overwrite_check() {
+    let dir = std::env::temp_dir().join(format!("browser-timer-rs-{}", std::process::id()));
+    let file = dir.join("output.csv");
+    let keep = OverwriteOptions { force: false };
+    let force = OverwriteOptions { force: true };
+
+    // missing and empty outputs can always be written
+    assert!(keep.check(&dir).is_ok());
+    std::fs::create_dir_all(&dir).unwrap();
+    assert!(keep.check(&dir).is_ok());
+    assert!(keep.check(&file).is_ok());
+
+    std::fs::write(&file, "i\n").unwrap();
+    assert!(keep.check(&file).is_err());
+    assert!(keep.check(&dir).is_err());
+    assert!(force.check_all([&file, &dir]).is_ok());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
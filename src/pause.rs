@@ -0,0 +1,114 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    abort,
+    clock::Clock,
+    hotkey::{self, HotKey},
+};
+
+/// Toggled by the pause hotkey.
+static PAUSE: AtomicBool = AtomicBool::new(false);
+
+/// How often a paused run checks if it was resumed.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Command line options for pausing a run.
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct PauseOptions {
+    /// Specifies a key (e.g. `ScrollLock`) that pauses the run before the next sample, pressing it again resumes the run
+    #[arg(long)]
+    pub pause_key: Option<HotKey>,
+}
+
+impl PauseOptions {
+    /// Grab the pause hotkey, if given, its presses do not reach the browser.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the hotkey is already used for something else.
+    pub fn install(self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(key) = self.pause_key {
+            println!("Press {key} to pause / resume");
+            hotkey::register(key, toggle)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pause the run, or resume it if it is paused.
+fn toggle() {
+    if PAUSE.fetch_xor(true, Ordering::Relaxed) {
+        println!("Resuming...");
+    } else {
+        println!("Pausing before the next sample...");
+    }
+}
+
+/// One interval a run was paused for.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PauseRecord {
+    /// Sample (row or file) the run was paused before
+    pub before: String,
+    /// Seconds since the start of the run, when the run was paused
+    pub paused: f64,
+    /// Seconds since the start of the run, when the run was resumed
+    pub resumed: f64,
+}
+
+/// All pauses of a run, so they can be excluded from its progress and ETA.
+#[derive(Debug)]
+pub struct Pauses {
+    start: Duration,
+    records: Vec<PauseRecord>,
+}
+
+impl Pauses {
+    /// No pauses yet, for a run started at clock time `start`.
+    pub const fn new(start: Duration) -> Self {
+        Self {
+            start,
+            records: Vec::new(),
+        }
+    }
+
+    /// Block while the run is paused, called between samples.
+    /// Returns if the run was paused, an abort ends the pause.
+    pub fn wait(&mut self, clock: &dyn Clock, before: &str) -> bool {
+        if !PAUSE.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let paused = clock.elapsed_since(self.start);
+        println!("Paused before {before}, press the pause key again to resume");
+        while PAUSE.load(Ordering::Relaxed) && !abort::requested() {
+            clock.sleep(POLL_INTERVAL);
+        }
+        let resumed = clock.elapsed_since(self.start);
+        println!(
+            "Resumed after {:.1}s",
+            resumed.saturating_sub(paused).as_secs_f64()
+        );
+
+        self.records.push(PauseRecord {
+            before: before.to_owned(),
+            paused: paused.as_secs_f64(),
+            resumed: resumed.as_secs_f64(),
+        });
+        true
+    }
+
+    /// How long the run was paused in total.
+    pub fn total(&self) -> Duration {
+        self.records
+            .iter()
+            .map(|record| Duration::from_secs_f64(record.resumed - record.paused))
+            .sum()
+    }
+
+    pub fn records(&self) -> &[PauseRecord] {
+        &self.records
+    }
+}
@@ -1,51 +1,237 @@
 use std::{
-    fmt::Debug,
+    collections::BTreeMap,
+    fmt::{Debug, Display},
     fs::File,
     io::Write,
     path::Path,
-    time::{Duration, Instant},
+    sync::Arc,
+    time::Duration,
 };
 
 use enigo::{Enigo, Key, KeyboardControllable};
 
 use crate::{
-    delay::{delay_busy, delay_sleep},
+    abort::{self, HeldKeys},
+    calibration::Compensation,
+    clock::Clock,
+    dataset::{DatasetEvent, DatasetFormat, Recording},
+    delay::{delay_sleep, DelayStrategy},
+    event_log::{Direction, EventLog, EventRecord},
+    filter::RowFilter,
+    free_text_data::{KeyEncoding, Keystroke, Mapped},
+    pause::Pauses,
+    realtime::RealtimeOptions,
+    run_info::RunInfo,
+    scheduler::{Fired, Scheduler},
+    shard::ShardOptions,
+    stats::Summary,
     Error, DOWNLOAD_KEY,
 };
 
-#[allow(clippy::cast_precision_loss)]
-pub fn pw_simulation<R: AsRef<Path>>(
+/// How key timings of a password are rebuilt from the dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimingModel {
+    /// Keydowns follow the previous keyup after UD, keyups follow their keydown after H
+    Ud,
+    /// Keydowns follow the previous keydown after DD, keyups follow their keydown after H
+    Dd,
+    /// Only check that DD equals H + UD for every row and report rows that disagree (nothing is simulated)
+    Check,
+}
+
+/// Command line options selecting how key timings are rebuilt from the dataset.
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct TimingOptions {
+    /// Specifies which columns keydowns are scheduled from
+    #[arg(long, value_enum, default_value_t = TimingModel::Ud)]
+    pub timing_model: TimingModel,
+
+    /// Specifies how many seconds DD and H + UD may differ, before a row is reported by the 'check' timing model
+    #[arg(long, default_value_t = 0.000_2)]
+    pub tolerance: f64,
+}
+
+/// How the Shift key of shifted characters is pressed and released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModifierPolicy {
+    /// Shift is pressed 100ns before the key and released 100ns after the keydown
+    Instant,
+    /// Shift is pressed 'modifier-lead' before the keydown and released 'modifier-lag' after the keyup
+    Fixed,
+    /// Like 'fixed', but lead and lag are read from 'Lead.<key>' and 'Lag.<key>' columns where they exist
+    /// (an extension of this tool, the CMU dataset has no such columns)
+    Dataset,
+}
+
+/// Command line options selecting how modifier keys are simulated.
+#[derive(Debug, Clone, Copy, clap::Args, serde::Serialize)]
+pub struct ModifierOptions {
+    /// Specifies how the Shift key of shifted characters is pressed and released
+    #[arg(long = "modifier-policy", value_enum, default_value_t = ModifierPolicy::Instant)]
+    pub policy: ModifierPolicy,
+
+    /// Specifies how many seconds Shift is pressed before the keydown ('fixed' and 'dataset')
+    #[arg(long = "modifier-lead", default_value_t = 0.03)]
+    pub lead: f64,
+
+    /// Specifies how many seconds Shift is released after the keyup ('fixed' and 'dataset')
+    #[arg(long = "modifier-lag", default_value_t = 0.03)]
+    pub lag: f64,
+}
+
+impl ModifierOptions {
+    /// Lead and lag of Shift, using the dataset values if given and the policy allows it.
+    /// Returns `None` for instantaneous modifiers.
+    fn lead_lag(&self, lead: Option<f64>, lag: Option<f64>) -> Option<(f64, f64)> {
+        match self.policy {
+            ModifierPolicy::Instant => None,
+            ModifierPolicy::Fixed => Some((self.lead, self.lag)),
+            ModifierPolicy::Dataset => Some((lead.unwrap_or(self.lead), lag.unwrap_or(self.lag))),
+        }
+    }
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::too_many_arguments,
+    clippy::too_many_lines
+)]
+pub fn pw_simulation<R: AsRef<Path>, W: AsRef<Path>>(
     in_file: R,
-    out_file: R,
+    format: DatasetFormat,
+    out_file: W,
     sleep: f64,
     download: usize,
     warmup: bool,
-    mut skip: usize,
-    mut count: usize,
+    skip: usize,
+    count: Option<usize>,
+    shard: ShardOptions,
+    filter: &RowFilter,
+    strategy: DelayStrategy,
+    clock: &Arc<dyn Clock>,
+    event_log: Option<&Path>,
+    realtime: RealtimeOptions,
+    compensation: Option<&Compensation>,
+    timing: TimingOptions,
+    modifier: ModifierOptions,
+    resume: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("[Password simulation]");
-    skip = skip.min(20400);
-    count = count.min(20400);
+    // read all rows (1 row == 1 password)
+    let (keys, mut rows) = read_rows(in_file.as_ref(), format)?;
+    println!(
+        "Password keys: {}",
+        keys.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
 
-    if skip == 20400 {
-        println!("Skipping all passwords...");
-        return Ok(());
+    // keep only the rows passing all filters
+    if filter.is_active() {
+        let total = rows.len();
+        rows.retain(|row| filter.matches(&row.subject, row.session_index, row.rep));
+        println!("Filter {filter}: {} of {total} passwords", rows.len());
     }
 
-    if skip + count > 20400 {
-        return Err(Box::new(Error(format!(
-            "Skip and Count parameter are greater then total password count (20400): skip: {skip} + count: {count} = {}", skip + count 
-        ))));
+    // select the rows to simulate, either a shard or by skip and count
+    let range = if let Some(range) = shard.range(
+        &rows
+            .iter()
+            .map(|row| row.subject.as_str())
+            .collect::<Vec<_>>(),
+    ) {
+        println!(
+            "Shard {} ({:?}) of {} passwords",
+            shard.shard.expect("range of selected shard"),
+            shard.shard_mode,
+            rows.len()
+        );
+        range
+    } else {
+        let count = count.unwrap_or(rows.len().saturating_sub(skip));
+        if skip + count > rows.len() {
+            return Err(Box::new(Error(format!(
+                "Skip and Count parameter are greater then total password count ({}): skip: {skip} + count: {count} = {}",
+                rows.len(),
+                skip + count
+            ))));
+        }
+        skip..skip + count
+    };
+    if range.is_empty() {
+        println!("Skipping all passwords...");
+        return Ok(());
     }
     println!(
         "Simulating {} passwords. [{}..{}]",
-        count,
-        skip,
-        skip + count
+        range.len(),
+        range.start,
+        range.end
     );
 
-    // read all rows (1 row == 1 password)
-    let mut rows = read_data(in_file.as_ref())?;
+    // count selected passwords per subject
+    let mut subjects = BTreeMap::<String, usize>::new();
+    for row in &rows[range.clone()] {
+        *subjects.entry(row.subject.clone()).or_default() += 1;
+    }
+    for (subject, count) in &subjects {
+        println!("  {subject}: {count} passwords");
+    }
+
+    if modifier.policy == ModifierPolicy::Dataset
+        && keys.iter().any(|key| key.shift)
+        && rows
+            .iter()
+            .flat_map(|row| row.shift_lead.iter().chain(&row.shift_lag))
+            .all(Option::is_none)
+    {
+        println!(
+            "Warning: No 'Lead.<key>'/'Lag.<key>' columns found, using lead {}s and lag {}s",
+            modifier.lead, modifier.lag
+        );
+    }
+
+    if timing.timing_model == TimingModel::Check {
+        check_timings(&keys, &rows[range], timing.tolerance);
+        return Ok(());
+    }
+
+    // parameters that have to be the same, to resume a run
+    let params = serde_json::json!({
+        "input": in_file.as_ref(),
+        "rows": [range.start, range.end],
+        "filter": filter.is_active().then(|| filter.to_string()),
+        "shard": shard.shard.map(|shard| shard.to_string()),
+        "shard_mode": shard.shard.map(|_| shard.shard_mode),
+        "timing_model": timing.timing_model,
+        "modifier": modifier,
+        "compensation": compensation,
+        "sleep": sleep,
+        "download": download,
+        "warmup": warmup,
+    });
+
+    rows = rows[range].to_vec();
+
+    // continue after the last password written to the output
+    let mut run_info = if resume {
+        RunInfo::load_for_output(out_file.as_ref())?
+    } else {
+        RunInfo::for_output(out_file.as_ref())
+    };
+    let resume_from = if resume {
+        resume_position(out_file.as_ref(), &run_info, &params, &rows)?
+    } else {
+        0
+    };
+    if resume_from == rows.len() {
+        println!("All passwords already simulated...");
+        return Ok(());
+    }
+
     // create new keyboard
     let mut keyboard = Enigo::new();
 
@@ -53,29 +239,58 @@ pub fn pw_simulation<R: AsRef<Path>>(
     let mut output_file = File::options()
         .create(true)
         .write(true)
-        .append(false)
-        .truncate(true)
+        .append(resume)
+        .truncate(!resume)
         .open(out_file.as_ref())?;
 
-    // initilaize csv file
-    writeln!(&mut output_file, "i,should_take,took,subject,session,rep")?;
+    // the event log is written before the output row, drop the records of an unwritten row
+    if let (true, Some(path)) = (resume, event_log) {
+        let last = resume_from
+            .checked_sub(1)
+            .map(|i| rows[i].index.to_string());
+        EventLog::truncate_after(path, last.as_deref())?;
+    }
+
+    // open the per-event log
+    let mut event_log = EventLog::open_optional(event_log, resume)?;
+
+    // pin thread and raise its priority, record what is really in effect
+    run_info.set("realtime", realtime.apply())?;
+    run_info.set("params", &params)?;
+    run_info.set("format", format.resolve(in_file.as_ref())?)?;
+    run_info.set("subjects", subjects)?;
+    if resume_from > 0 {
+        let mut resumed = run_info
+            .get("resumed")
+            .and_then(|resumed| serde_json::from_value::<Vec<usize>>(resumed.clone()).ok())
+            .unwrap_or_default();
+        resumed.push(rows[resume_from].index);
+        run_info.set("resumed", resumed)?;
+    }
+    run_info.remove("interrupted");
+    run_info.save()?;
 
-    rows = rows[skip..skip + count].to_vec();
+    // initilaize csv file
+    if output_file.metadata()?.len() == 0 {
+        output_file
+            .write_all(b"i,should_take,took,subject,session,rep,mean_lateness,max_lateness\n")?;
+    }
 
     // calculate total time needed
     let mut total = 0.0;
-    for row in &rows {
+    for row in &rows[resume_from..] {
         total += row.should_take().as_secs_f64();
         total += sleep;
     }
+    let remaining = rows.len() - resume_from;
     // how many downloads * waits
-    total += (rows.len() / download) as f64 * 0.8;
+    total += (remaining / download) as f64 * 0.8;
     // if there is a rest to download
-    total += (rows.len() % download).min(1) as f64 * 0.8;
+    total += (remaining % download).min(1) as f64 * 0.8;
 
     if warmup {
-        total += rows.len() as f64 * 8.0 * 0.100;
-        total += rows.len() as f64 * 8.0 * 0.010;
+        total += remaining as f64 * 8.0 * 0.100;
+        total += remaining as f64 * 8.0 * 0.010;
     }
 
     // calculate time needed as hours
@@ -87,56 +302,111 @@ pub fn pw_simulation<R: AsRef<Path>>(
 
     println!("Waiting for user to be ready (10 sec)...");
     // wait for user to be ready
-    delay_sleep(10.0);
+    delay_sleep(&**clock, 10.0);
     println!("Starting...");
 
+    // passwords simulated after the last download may still be held by the webapp
+    let last = resume_from.checked_sub(1);
+    if last.is_some_and(|last| last == 0 || last % download != 0) {
+        println!("Triggering download of passwords simulated before resuming...");
+        keyboard.key_down(DOWNLOAD_KEY);
+        keyboard.key_up(DOWNLOAD_KEY);
+        delay_sleep(&**clock, 1.5);
+    }
+
     // save total start time
-    let start_time = Instant::now();
+    let start_time = clock.now();
+    // time spent paused is excluded from the progress
+    let mut pauses = Pauses::new(start_time);
+
+    // how late each event was executed, compared to its scheduled time
+    let mut all_lateness = Vec::new();
+
+    // keys held down by the current password, released if the run is aborted
+    let mut held = HeldKeys::default();
+    // row the run was aborted at
+    let mut interrupted = None;
 
     // iterate each row/password
-    for (i, row) in rows.iter().enumerate() {
+    for (i, row) in rows.iter().enumerate().skip(resume_from) {
+        if pauses.wait(&**clock, &format!("row {}", row.index)) {
+            run_info.set("pauses", pauses.records())?;
+            run_info.save()?;
+        }
+        if abort::requested() {
+            interrupted = Some((i, row.index));
+            break;
+        }
+
         // create precalculated event list, ordered by time
-        let events = row.create_events();
+        let events = row.create_events(&keys, timing.timing_model, &modifier);
 
         // warmup phase
         if warmup {
             for _ in 0..8 {
                 keyboard.key_down(Key::Layout('q'));
-                delay_busy(0.100);
+                strategy.delay(&**clock, 0.100);
                 keyboard.key_up(Key::Layout('q'));
-                delay_sleep(0.010);
+                delay_sleep(&**clock, 0.010);
             }
         }
 
-        // save password start time
-        let now = Instant::now();
-        // .tie5Roanl
-        // iterate each input event for row/password
-        for (i, event) in events.iter().enumerate() {
-            // execute event
-            event.execute(&mut keyboard);
-            // if there is next event/not last
-            if let Some(next_event) = events.get(i + 1) {
-                // calculate how long until the next event
-                let sleep = next_event.timestamp - event.timestamp;
-                // sleep until next event should be executed
-                delay_busy(sleep);
-            }
+        // anchor all events of this password at the password start time,
+        // leaving room to inject events earlier by their latency
+        // and a leading Shift, so the first keydown stays the anchor that `should_take` starts at
+        let shift_lead = -events.first().map_or(0.0, |event| event.timestamp.min(0.0));
+        let lead = compensation.map_or(0.0, |c| c.lead) + shift_lead;
+        let scheduler = Scheduler::start_with_lead(clock, strategy, lead);
+        // iterate each input event for row/password, execute it when it is scheduled
+        // stop injecting as soon as the run is aborted
+        let fired = events
+            .iter()
+            .map_while(|event| {
+                let (key, direction) = event.key_event.key_direction();
+                let offset = compensation.map_or(0.0, |c| c.offset(key, direction));
+                let fired =
+                    scheduler.fire_at(event.timestamp - offset, || event.execute(&mut keyboard))?;
+                held.track(key, direction);
+                Some(fired)
+            })
+            .collect::<Vec<_>>();
+        if fired.len() < events.len() {
+            interrupted = Some((i, row.index));
+            break;
         }
         // check how long it took to simulate password
-        let elapsed = now.elapsed().as_secs_f64();
+        let elapsed = scheduler.elapsed();
         // get how long it should take to simulate password
         let should_take = row.should_take().as_secs_f64();
 
-        // write timing data to output file
-        writeln!(
-            &mut output_file,
-            "{},{should_take},{elapsed},{},{},{}",
-            i + skip,
-            row.subject,
-            row.session_index,
-            row.rep
-        )?;
+        let lateness = fired.iter().map(Fired::lateness).collect::<Vec<_>>();
+        // events are never empty
+        let summary = Summary::new(&lateness).expect("password without events");
+        all_lateness.extend(lateness);
+
+        // write per-event timing data to event log
+        if let Some(log) = &mut event_log {
+            let sample = row.index.to_string();
+            let records = events
+                .iter()
+                .zip(&fired)
+                .enumerate()
+                .map(|(n, (event, fired))| {
+                    let (key, direction) = event.key_event.key_direction();
+                    EventRecord::from_fired(&sample, n, key, direction, fired)
+                })
+                .collect::<Vec<_>>();
+            log.write_all(&records)?;
+            log.flush()?;
+        }
+
+        // write timing data to output file, as a whole line, so a crash can not leave half a row
+        let line = format!(
+            "{},{should_take},{elapsed},{},{},{},{},{}\n",
+            row.index, row.subject, row.session_index, row.rep, summary.mean, summary.max
+        );
+        output_file.write_all(line.as_bytes())?;
+        output_file.sync_data()?;
 
         // log progess
         println!(
@@ -144,7 +414,11 @@ pub fn pw_simulation<R: AsRef<Path>>(
             i + 1,
             rows.len(),
             (i as f32 / rows.len() as f32) * 100.0,
-            start_time.elapsed().as_secs_f64() / 3600.0
+            clock
+                .elapsed_since(start_time)
+                .saturating_sub(pauses.total())
+                .as_secs_f64()
+                / 3600.0
         );
 
         // every 1000 passwords, trigger download
@@ -153,197 +427,768 @@ pub fn pw_simulation<R: AsRef<Path>>(
             keyboard.key_down(DOWNLOAD_KEY);
             keyboard.key_up(DOWNLOAD_KEY);
             // wait a bit for download to finish
-            delay_sleep(1.5);
+            delay_sleep(&**clock, 1.5);
         }
 
         // wait a bit before beginning with next password simulation
-        delay_sleep(sleep);
+        delay_sleep(&**clock, sleep);
+    }
+
+    // the interrupted password is not written, so a resumed run repeats it
+    if let Some((i, index)) = interrupted {
+        held.release_all(&mut keyboard);
+        if let Some(log) = &mut event_log {
+            log.flush()?;
+        }
+        output_file.flush()?;
+
+        run_info.set(
+            "interrupted",
+            serde_json::json!({ "row": index, "completed": i - resume_from }),
+        )?;
+        run_info.save()?;
+        return Err(Box::new(Error(format!(
+            "Interrupted at row {index} ({} / {}), continue with --resume",
+            i,
+            rows.len()
+        ))));
     }
 
     // trigger download for rest of data
     keyboard.key_down(DOWNLOAD_KEY);
     keyboard.key_up(DOWNLOAD_KEY);
+
+    if let Some(log) = &mut event_log {
+        log.flush()?;
+    }
+
+    if let Some(summary) = Summary::new(&all_lateness) {
+        println!("Event lateness: {summary}");
+    }
     Ok(())
 }
 
-/// Reads CSV-File and returns vector of rows.
+/// Position in `rows` to resume a run from, the row after the last one written to `output`.
+/// A partially written last row is dropped from `output`.
 ///
 /// # Errors
 ///
-/// Returns an error, if file could not be opened or failed parsing CSV-File into rows.
-fn read_data<R: AsRef<Path>>(file: R) -> Result<Vec<Row>, Box<dyn std::error::Error>> {
+/// Returns an error, if the parameters of the previous run differ from `params`
+/// or the output contains a row that is not part of `rows`.
+fn resume_position(
+    output: &Path,
+    run_info: &RunInfo,
+    params: &serde_json::Value,
+    rows: &[Row],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if !output.exists() {
+        println!("Nothing to resume, output does not exist yet");
+        return Ok(0);
+    }
+
+    // all parameters have to match the previous run
+    let previous = run_info
+        .get("params")
+        .and_then(serde_json::Value::as_object);
+    let Some(previous) = previous else {
+        return Err(Box::new(Error(
+            "Can not resume, no parameters of the previous run found".into(),
+        )));
+    };
+    let mismatched = params
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| {
+            let before = previous.get(key).unwrap_or(&serde_json::Value::Null);
+            (before != value).then(|| format!("{key}: {before} -> {value}"))
+        })
+        .collect::<Vec<_>>();
+    if !mismatched.is_empty() {
+        return Err(Box::new(Error(format!(
+            "Can not resume, parameters differ from the previous run: {}",
+            mismatched.join(", ")
+        ))));
+    }
+
+    // last completed password
+    crate::output::drop_partial_line(output)?;
+    let mut reader = csv::Reader::from_path(output)?;
+    let mut last = None;
+    for record in reader.records() {
+        last = Some(record?.get(0).unwrap_or_default().parse::<usize>()?);
+    }
+    let Some(last) = last else {
+        return Ok(0);
+    };
+
+    let position = rows
+        .iter()
+        .position(|row| row.index == last)
+        .ok_or_else(|| Error(format!("Can not resume, row {last} is not selected")))?;
+    println!(
+        "Resuming after row {last} ({} / {})",
+        position + 1,
+        rows.len()
+    );
+    Ok(position + 1)
+}
+
+/// Reads CSV-File and returns the password keys (from the header) and vector of rows.
+///
+/// # Errors
+///
+/// Returns an error, if file could not be opened, the header does not describe a password
+/// or failed parsing CSV-File into rows.
+fn read_data<R: AsRef<Path>>(
+    file: R,
+) -> Result<(Vec<PasswordKey>, Vec<Row>), Box<dyn std::error::Error>> {
     // open input file
     let file = std::fs::File::open(file.as_ref())?;
 
     let mut reader = csv::Reader::from_reader(file);
 
+    // find password keys and their columns
+    let layout = Layout::from_header(reader.headers()?)?;
+
     // read csv file
-    let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
 
     // map raw csv to `Row` struct
-    let rows = rows
+    let rows = records
         .iter()
-        .map(|row| row.deserialize(None))
-        .collect::<Result<Vec<Row>, _>>()?;
+        .enumerate()
+        .map(|(i, record)| layout.row(i, record))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(rows)
+    Ok((layout.keys, rows))
 }
 
-/// Struct describing password input
-#[derive(Debug, Clone, serde::Deserialize)]
-struct Row {
-    pub subject: String,
-    pub session_index: u64,
-    pub rep: u64,
-    pub h_period: f64,
-    pub __dd_period_t: f64,
-    pub ud_period_t: f64,
-    pub h_t: f64,
-    pub _dd_t_i: f64,
-    pub ud_t_i: f64,
-    pub h_i: f64,
-    pub _dd_i_e: f64,
-    pub ud_i_e: f64,
-    pub h_e: f64,
-    pub _dd_e_five: f64,
-    pub ud_e_five: f64,
-    pub h_five: f64,
-    pub _dd_five_shift_r: f64,
-    pub ud_five_shift_r: f64,
-    pub h_shift_r: f64,
-    pub _dd_shift_r_o: f64,
-    pub ud_shift_r_o: f64,
-    pub h_o: f64,
-    pub _dd_o_a: f64,
-    pub ud_o_a: f64,
-    pub h_a: f64,
-    pub _dd_a_n: f64,
-    pub ud_a_n: f64,
-    pub h_n: f64,
-    pub _dd_n_l: f64,
-    pub ud_n_l: f64,
-    pub h_l: f64,
-    pub _dd_l_return: f64,
-    pub ud_l_return: f64,
-    pub h_return: f64,
+/// Reads the password keys and rows of a dataset in any format.
+/// CMU CSV-Files are read directly, so DD and UD keep their recorded values.
+///
+/// # Errors
+///
+/// Returns an error, if the dataset could not be read, or its recordings do not type the same password.
+fn read_rows(
+    file: &Path,
+    format: DatasetFormat,
+) -> Result<(Vec<PasswordKey>, Vec<Row>), Box<dyn std::error::Error>> {
+    match format.resolve(file)? {
+        DatasetFormat::Cmu => read_data(file),
+        _ => Ok(rows_from_recordings(&format.read(file)?)?),
+    }
 }
 
-impl Row {
-    /// Calculate how long it should take to simulate row
-    pub fn should_take(&self) -> Duration {
-        let mut total = 0.0;
-        // .tie5Roanl
-        total += self.h_period;
-        total += self.h_t;
-        total += self.h_i;
-        total += self.h_e;
-        total += self.h_five;
-        total += self.h_shift_r;
-        total += self.h_o;
-        total += self.h_a;
-        total += self.h_n;
-        total += self.h_l;
-        total += self.h_return;
-
-        total += self.ud_period_t;
-        total += self.ud_t_i;
-        total += self.ud_i_e;
-        total += self.ud_e_five;
-        total += self.ud_five_shift_r;
-        total += self.ud_shift_r_o;
-        total += self.ud_o_a;
-        total += self.ud_a_n;
-        total += self.ud_n_l;
-        total += self.ud_l_return;
+/// Reads CSV-File as recordings of key events, one per row.
+///
+/// # Errors
+///
+/// Returns an error, if the file could not be read, see [`read_data`].
+pub fn read_recordings<R: AsRef<Path>>(
+    file: R,
+) -> Result<Vec<Recording>, Box<dyn std::error::Error>> {
+    let (keys, rows) = read_data(file)?;
+    let codes = keys
+        .iter()
+        .map(|key| {
+            KeyEncoding::key_code(key.key).ok_or_else(|| Error(format!("No key code for {key}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-        Duration::from_secs_f64(total)
+    Ok(rows
+        .iter()
+        .map(|row| row.recording(&keys, &codes))
+        .collect())
+}
+
+/// Key press of a recording, times in ms.
+#[derive(Debug)]
+struct Press {
+    key: PasswordKey,
+    down: f64,
+    up: Option<f64>,
+    /// When Shift was pressed (and released) around the key
+    shift: Option<(f64, Option<f64>)>,
+}
+
+/// Key presses of a recording, keys pressed while Shift is held down are shifted.
+fn recording_presses(recording: &Recording) -> Result<Vec<Press>, Error> {
+    let keystrokes = recording
+        .events
+        .iter()
+        .filter(|event| event.direction == Direction::Down)
+        .map(|event| Keystroke {
+            timestamp: 0,
+            code: event.code,
+            hold: None,
+            line: None,
+        })
+        .collect::<Vec<_>>();
+    let encoding = KeyEncoding::detect(&keystrokes);
+
+    let mut presses: Vec<Press> = Vec::new();
+    let mut shift_down = None;
+    for event in &recording.events {
+        match (encoding.map(event.code), event.direction) {
+            (Mapped::Shift, Direction::Down) => {
+                shift_down.get_or_insert(event.timestamp);
+            }
+            (Mapped::Shift, Direction::Up) => {
+                if let Some(down) = shift_down.take() {
+                    for press in &mut presses {
+                        if press.shift == Some((down, None)) {
+                            press.shift = Some((down, Some(event.timestamp)));
+                        }
+                    }
+                }
+            }
+            (Mapped::Key { key, shift }, Direction::Down) => presses.push(Press {
+                key: PasswordKey {
+                    key,
+                    shift: shift || shift_down.is_some(),
+                },
+                down: event.timestamp,
+                up: None,
+                shift: shift_down.map(|down| (down, None)),
+            }),
+            (Mapped::Key { key, .. }, Direction::Up) => {
+                let press = presses
+                    .iter_mut()
+                    .rev()
+                    .find(|press| press.key.key == key && press.up.is_none());
+                if let Some(press) = press {
+                    press.up = Some(event.timestamp);
+                }
+            }
+            (Mapped::Unmapped, _) => {
+                return Err(Error(format!(
+                    "Key code {} in recording {} can not be simulated",
+                    event.code, recording.name
+                )))
+            }
+        }
     }
 
-    /// Precalculate input events, ordered by time.
-    pub fn create_events(&self) -> Vec<Event> {
-        let mut events = Vec::new();
+    Ok(presses)
+}
 
-        let mut timestamp = 0.0;
+/// Rows of recordings of a password (e.g. from a TSV or JSON lines dataset), all recordings have to type the same keys.
+/// Shift lead and lag are taken from the Shift events around shifted keys.
+///
+/// # Errors
+///
+/// Returns an error, if a recording types other keys, or a key is never released.
+fn rows_from_recordings(recordings: &[Recording]) -> Result<(Vec<PasswordKey>, Vec<Row>), Error> {
+    let join = |keys: &[PasswordKey]| {
+        keys.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    // ms to seconds
+    let seconds = |ms: f64| ms / 1000.0;
+
+    let mut password: Option<Vec<PasswordKey>> = None;
+    // repetitions of each subject and session, if the dataset does not number them
+    let mut reps = BTreeMap::<(String, u64), u64>::new();
+    let mut rows = Vec::with_capacity(recordings.len());
+
+    for (index, recording) in recordings.iter().enumerate() {
+        let presses = recording_presses(recording)?;
+        let keys = presses.iter().map(|press| press.key).collect::<Vec<_>>();
+        let expected = password.get_or_insert_with(|| keys.clone());
+        if *expected != keys {
+            return Err(Error(format!(
+                "Recording {} types '{}' instead of the password '{}'",
+                recording.name,
+                join(&keys),
+                join(expected)
+            )));
+        }
 
-        // .tie5Roanl
+        let ups = presses
+            .iter()
+            .map(|press| {
+                press.up.ok_or_else(|| {
+                    Error(format!(
+                        "Key {} in recording {} is never released",
+                        press.key, recording.name
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let metadata = |name: &str| recording.metadata.get(name);
+        let subject = metadata("subject").map_or_else(|| recording.name.clone(), Clone::clone);
+        let session_index = metadata("session")
+            .and_then(|session| session.parse().ok())
+            .unwrap_or(1);
+        let rep = reps.entry((subject.clone(), session_index)).or_default();
+        *rep += 1;
+        let rep = metadata("rep")
+            .and_then(|rep| rep.parse().ok())
+            .unwrap_or(*rep);
+
+        rows.push(Row {
+            index,
+            subject,
+            session_index,
+            rep,
+            hold: presses
+                .iter()
+                .zip(&ups)
+                .map(|(press, up)| seconds(up - press.down))
+                .collect(),
+            down_down: presses
+                .windows(2)
+                .map(|pair| seconds(pair[1].down - pair[0].down))
+                .collect(),
+            up_down: presses
+                .iter()
+                .skip(1)
+                .zip(&ups)
+                .map(|(next, up)| seconds(next.down - up))
+                .collect(),
+            shift_lead: presses
+                .iter()
+                .map(|press| press.shift.map(|(down, _)| seconds(press.down - down)))
+                .collect(),
+            shift_lag: presses
+                .iter()
+                .zip(&ups)
+                .map(|(press, up)| {
+                    press
+                        .shift
+                        .and_then(|(_, shift_up)| Some(seconds(shift_up? - up)))
+                })
+                .collect(),
+        });
+    }
 
-        // .
-        events.push(Event::new_down(timestamp, Key::Layout('.')));
-        timestamp += self.h_period;
-        events.push(Event::new_up(timestamp, Key::Layout('.')));
+    let keys = password.ok_or_else(|| Error("The dataset has no recordings".to_owned()))?;
+    Ok((keys, rows))
+}
 
-        timestamp += self.ud_period_t;
+/// Hold times (in seconds) of each key of the password, one per row.
+pub type HoldTimes = Vec<(PasswordKey, Vec<f64>)>;
 
-        // t
-        events.push(Event::new_down(timestamp, Key::Layout('t')));
-        timestamp += self.h_t;
-        events.push(Event::new_up(timestamp, Key::Layout('t')));
+/// Reads the hold times of every password key from a CSV-File (its `H.<key>` columns).
+///
+/// # Errors
+///
+/// Returns an error, if the file could not be read, see [`read_data`].
+pub fn read_hold_times<R: AsRef<Path>>(file: R) -> Result<HoldTimes, Box<dyn std::error::Error>> {
+    let (keys, rows) = read_data(file)?;
+
+    let holds = keys
+        .into_iter()
+        .enumerate()
+        .map(|(k, key)| (key, rows.iter().map(|row| row.hold[k]).collect()))
+        .collect();
+    Ok(holds)
+}
 
-        timestamp += self.ud_t_i;
+/// Columns of the CSV-File, derived from its header.
+///
+/// The header follows the CMU format: `subject,sessionIndex,rep`, followed by
+/// `H.<key>` for every key and `DD.<key>.<next>`/`UD.<key>.<next>` for every pair of consecutive keys.
+#[derive(Debug)]
+struct Layout {
+    keys: Vec<PasswordKey>,
+    subject: usize,
+    session_index: usize,
+    rep: usize,
+    hold: Vec<usize>,
+    down_down: Vec<usize>,
+    up_down: Vec<usize>,
+    /// Optional columns with the Shift lead/lag of each key
+    shift_lead: Vec<Option<usize>>,
+    shift_lag: Vec<Option<usize>>,
+}
 
-        // i
-        events.push(Event::new_down(timestamp, Key::Layout('i')));
-        timestamp += self.h_i;
-        events.push(Event::new_up(timestamp, Key::Layout('i')));
+impl Layout {
+    fn from_header(header: &csv::StringRecord) -> Result<Self, Error> {
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| Error(format!("Missing column '{name}' in CSV header")))
+        };
+
+        // keys in order of their hold columns
+        let names = header
+            .iter()
+            .filter_map(|h| h.strip_prefix("H."))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Err(Error("No 'H.<key>' columns in CSV header".into()));
+        }
 
-        timestamp += self.ud_i_e;
+        let keys = names
+            .iter()
+            .map(|name| {
+                PasswordKey::from_name(name)
+                    .ok_or_else(|| Error(format!("Unknown key '{name}' in CSV header")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let hold = names
+            .iter()
+            .map(|name| column(&format!("H.{name}")))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut down_down = Vec::with_capacity(names.len() - 1);
+        let mut up_down = Vec::with_capacity(names.len() - 1);
+        for pair in names.windows(2) {
+            down_down.push(column(&format!("DD.{}.{}", pair[0], pair[1]))?);
+            up_down.push(column(&format!("UD.{}.{}", pair[0], pair[1]))?);
+        }
 
-        // e
-        events.push(Event::new_down(timestamp, Key::Layout('e')));
-        timestamp += self.h_e;
-        events.push(Event::new_up(timestamp, Key::Layout('e')));
+        let optional = |prefix: &str| {
+            names
+                .iter()
+                .map(|name| column(&format!("{prefix}.{name}")).ok())
+                .collect::<Vec<_>>()
+        };
+
+        Ok(Self {
+            shift_lead: optional("Lead"),
+            shift_lag: optional("Lag"),
+            keys,
+            subject: column("subject")?,
+            session_index: column("sessionIndex")?,
+            rep: column("rep")?,
+            hold,
+            down_down,
+            up_down,
+        })
+    }
 
-        timestamp += self.ud_e_five;
+    fn row(
+        &self,
+        index: usize,
+        record: &csv::StringRecord,
+    ) -> Result<Row, Box<dyn std::error::Error>> {
+        let field = |i: usize| record.get(i).unwrap_or_default();
+        let times = |columns: &[usize]| {
+            columns
+                .iter()
+                .map(|i| field(*i).parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let optional_times = |columns: &[Option<usize>]| {
+            columns
+                .iter()
+                .map(|i| i.map(|i| field(i).parse::<f64>()).transpose())
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(Row {
+            index,
+            subject: field(self.subject).to_owned(),
+            session_index: field(self.session_index).parse()?,
+            rep: field(self.rep).parse()?,
+            hold: times(&self.hold)?,
+            down_down: times(&self.down_down)?,
+            up_down: times(&self.up_down)?,
+            shift_lead: optional_times(&self.shift_lead)?,
+            shift_lag: optional_times(&self.shift_lag)?,
+        })
+    }
+}
 
-        // 5
-        events.push(Event::new_down(timestamp, Key::Layout('5')));
-        timestamp += self.h_five;
-        events.push(Event::new_up(timestamp, Key::Layout('5')));
+/// Pairs of shifted characters and the key they are typed with (US layout).
+const SHIFTED: &str = "!1@2#3$4%5^6&7*8(9)0_-+={[}]|\\:;\"'<,>.?/~`";
 
-        timestamp += self.ud_five_shift_r;
+/// Key of the password, as named in the CSV header (e.g. `period`, `five`, `Shift.r`, `Return`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordKey {
+    pub key: Key,
+    /// If shift has to be held down while pressing the key
+    pub shift: bool,
+}
 
-        // R
-        events.push(Event::new_down(timestamp, Key::Shift));
-        events.push(Event::new_down(timestamp + 0.000_000_1, Key::Layout('r')));
-        events.push(Event::new_up(timestamp + 0.000_000_2, Key::Shift));
-        timestamp += self.h_shift_r;
-        events.push(Event::new_up(timestamp, Key::Layout('r')));
+impl PasswordKey {
+    /// Parse the name of a key used in the CSV header.
+    fn from_name(name: &str) -> Option<Self> {
+        if let Some(name) = name.strip_prefix("Shift.") {
+            return Self::from_name(name).map(|key| Self { shift: true, ..key });
+        }
 
-        timestamp += self.ud_shift_r_o;
+        let key = match name {
+            "Return" | "Enter" => Key::Return,
+            "Tab" => Key::Tab,
+            "space" | "Space" => Key::Space,
+            "BackSpace" | "Backspace" => Key::Backspace,
+            _ => return named_char(name).map(Self::from_char),
+        };
+        Some(Self { key, shift: false })
+    }
 
-        // o
-        events.push(Event::new_down(timestamp, Key::Layout('o')));
-        timestamp += self.h_o;
-        events.push(Event::new_up(timestamp, Key::Layout('o')));
+    /// Key for typing `c`, shifted characters are typed as Shift+key (US layout).
+    fn from_char(c: char) -> Self {
+        if c.is_ascii_uppercase() {
+            return Self {
+                key: Key::Layout(c.to_ascii_lowercase()),
+                shift: true,
+            };
+        }
 
-        timestamp += self.ud_o_a;
+        let unshifted = SHIFTED
+            .chars()
+            .step_by(2)
+            .zip(SHIFTED.chars().skip(1).step_by(2))
+            .find_map(|(shifted, base)| (shifted == c).then_some(base));
+
+        match unshifted {
+            Some(base) => Self {
+                key: Key::Layout(base),
+                shift: true,
+            },
+            None => Self {
+                key: Key::Layout(c),
+                shift: false,
+            },
+        }
+    }
+}
 
-        // a
-        events.push(Event::new_down(timestamp, Key::Layout('a')));
-        timestamp += self.h_a;
-        events.push(Event::new_up(timestamp, Key::Layout('a')));
+impl Display for PasswordKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        match self.key {
+            Key::Layout(c) => write!(f, "{c}"),
+            key => write!(f, "{key:?}"),
+        }
+    }
+}
 
-        timestamp += self.ud_a_n;
+/// Character named in the CSV header, either the character itself or its name (e.g. `period`, `five`).
+fn named_char(name: &str) -> Option<char> {
+    let c = match name {
+        "period" => '.',
+        "comma" => ',',
+        "minus" => '-',
+        "equal" => '=',
+        "slash" => '/',
+        "backslash" => '\\',
+        "semicolon" => ';',
+        "apostrophe" => '\'',
+        "grave" => '`',
+        "bracketleft" => '[',
+        "bracketright" => ']',
+        "zero" => '0',
+        "one" => '1',
+        "two" => '2',
+        "three" => '3',
+        "four" => '4',
+        "five" => '5',
+        "six" => '6',
+        "seven" => '7',
+        "eight" => '8',
+        "nine" => '9',
+        _ => {
+            let mut chars = name.chars();
+            let c = chars.next()?;
+            return chars.next().is_none().then_some(c);
+        }
+    };
+    Some(c)
+}
 
-        // n
-        events.push(Event::new_down(timestamp, Key::Layout('n')));
-        timestamp += self.h_n;
-        events.push(Event::new_up(timestamp, Key::Layout('n')));
+/// Check that DD equals H + UD for every pair of keys in `rows`, report all that differ more than `tolerance`.
+#[allow(clippy::cast_precision_loss)]
+fn check_timings(keys: &[PasswordKey], rows: &[Row], tolerance: f64) {
+    let mut differences = Vec::new();
+    let mut disagreeing = 0;
+
+    for row in rows {
+        let mut row_disagrees = false;
+        for (k, pair) in keys.windows(2).enumerate() {
+            let difference = row.down_down[k] - (row.hold[k] + row.up_down[k]);
+            differences.push(difference.abs());
+
+            if difference.abs() > tolerance {
+                row_disagrees = true;
+                println!(
+                    "Row {} ({} session {} rep {}) {} -> {}: DD {} != H {} + UD {} (off by {:.1}µs)",
+                    row.index,
+                    row.subject,
+                    row.session_index,
+                    row.rep,
+                    pair[0],
+                    pair[1],
+                    row.down_down[k],
+                    row.hold[k],
+                    row.up_down[k],
+                    difference * 1e6
+                );
+            }
+        }
+        disagreeing += usize::from(row_disagrees);
+    }
+
+    println!(
+        "{disagreeing} / {} rows disagree beyond {:.1}µs",
+        rows.len(),
+        tolerance * 1e6
+    );
+    if let Some(summary) = Summary::new(&differences) {
+        println!("|DD - (H + UD)|: {summary}");
+    }
+}
+
+/// Struct describing password input
+#[derive(Debug, Clone)]
+struct Row {
+    /// Position of the row in the dataset
+    pub index: usize,
+    pub subject: String,
+    pub session_index: u64,
+    pub rep: u64,
+    /// Hold time of each key
+    pub hold: Vec<f64>,
+    /// Time between pressing each key and pressing the next one
+    pub down_down: Vec<f64>,
+    /// Time between releasing each key and pressing the next one
+    pub up_down: Vec<f64>,
+    /// Time Shift is pressed before each key (if given by the dataset)
+    pub shift_lead: Vec<Option<f64>>,
+    /// Time Shift is released after each key (if given by the dataset)
+    pub shift_lag: Vec<Option<f64>>,
+}
+
+impl Row {
+    /// Calculate how long it should take to simulate row
+    pub fn should_take(&self) -> Duration {
+        let total = self.hold.iter().sum::<f64>() + self.up_down.iter().sum::<f64>();
+        Duration::from_secs_f64(total)
+    }
+
+    /// Key events of the row (in ms), keydowns follow the previous keydown after DD.
+    /// Shift is pressed 'Lead' before and released 'Lag' after shifted keys, where given.
+    fn recording(&self, keys: &[PasswordKey], codes: &[u8]) -> Recording {
+        // seconds to ms
+        let event = |timestamp: f64, code, direction| DatasetEvent {
+            timestamp: timestamp * 1000.0,
+            code,
+            direction,
+            line: None,
+        };
+        let shift = KeyEncoding::key_code(Key::Shift).unwrap_or(16);
+
+        let mut events = Vec::with_capacity(keys.len() * 4);
+        let mut timestamp = 0.0;
+        for (i, (key, code)) in keys.iter().zip(codes).enumerate() {
+            let keyup = timestamp + self.hold[i];
+            if key.shift {
+                let lead = self.shift_lead[i].unwrap_or_default();
+                events.push(event(timestamp - lead, shift, Direction::Down));
+            }
+            events.push(event(timestamp, *code, Direction::Down));
+            events.push(event(keyup, *code, Direction::Up));
+            if key.shift {
+                let lag = self.shift_lag[i].unwrap_or_default();
+                events.push(event(keyup + lag, shift, Direction::Up));
+            }
+            timestamp += self.down_down.get(i).copied().unwrap_or(self.hold[i]);
+        }
 
-        timestamp += self.ud_n_l;
+        // a leading Shift may start before the first key
+        let first = events
+            .iter()
+            .map(|event| event.timestamp)
+            .fold(0.0, f64::min);
+        for event in &mut events {
+            event.timestamp -= first;
+        }
 
-        // l
-        events.push(Event::new_down(timestamp, Key::Layout('l')));
-        timestamp += self.h_l;
-        events.push(Event::new_up(timestamp, Key::Layout('l')));
+        let mut recording = Recording::new(
+            format!("{}-{}-{}", self.subject, self.session_index, self.rep),
+            events,
+        );
+        recording.metadata = BTreeMap::from([
+            ("subject".to_owned(), self.subject.clone()),
+            ("session".to_owned(), self.session_index.to_string()),
+            ("rep".to_owned(), self.rep.to_string()),
+            ("row".to_owned(), self.index.to_string()),
+        ]);
+        recording
+    }
 
-        timestamp += self.ud_l_return;
+    /// Precalculate input events, ordered by time.
+    /// Keydowns are scheduled according to `model`, keyups always `H` after their keydown.
+    /// Shift of shifted keys is pressed and released according to `modifier`.
+    ///
+    /// A leading Shift may be scheduled before the first keydown (negative timestamp).
+    /// Shift is never held down over an unshifted keydown, overlapping holds of
+    /// consecutive shifted keys are merged into one.
+    pub fn create_events(
+        &self,
+        keys: &[PasswordKey],
+        model: TimingModel,
+        modifier: &ModifierOptions,
+    ) -> Vec<Event> {
+        // keydown and keyup of every key
+        let mut times = Vec::with_capacity(keys.len());
+        let mut timestamp = 0.0;
+        for i in 0..keys.len() {
+            times.push((timestamp, timestamp + self.hold[i]));
+
+            // time until the next keydown
+            timestamp += match model {
+                TimingModel::Dd => self.down_down.get(i).copied().unwrap_or(self.hold[i]),
+                TimingModel::Ud | TimingModel::Check => {
+                    self.hold[i] + self.up_down.get(i).copied().unwrap_or_default()
+                }
+            };
+        }
 
-        // return
-        events.push(Event::new_down(timestamp, Key::Return));
-        timestamp += self.h_return;
-        events.push(Event::new_up(timestamp, Key::Return));
+        // Shift holds, with lead and lag limited to the previous keyup and the next keydown
+        let mut shift: Vec<(f64, f64)> = Vec::new();
+        let mut key_events = Vec::with_capacity(keys.len() * 2);
+        for (i, (key, (down, up))) in keys.iter().zip(times.iter().copied()).enumerate() {
+            if key.shift {
+                match modifier.lead_lag(self.shift_lead[i], self.shift_lag[i]) {
+                    None => {
+                        key_events.push(Event::new_down(down, Key::Shift));
+                        key_events.push(Event::new_down(down + 0.000_000_1, key.key));
+                        key_events.push(Event::new_up(down + 0.000_000_2, Key::Shift));
+                        key_events.push(Event::new_up(up, key.key));
+                        continue;
+                    }
+                    Some((lead, lag)) => {
+                        let previous_up = i.checked_sub(1).map_or(f64::MIN, |p| times[p].1);
+                        let next_down = times.get(i + 1).map_or(f64::MAX, |next| next.0);
+                        let pressed = (down - lead).max(previous_up.min(down));
+                        let released = (up + lag).min(next_down);
+                        match shift.last_mut() {
+                            Some(last) if pressed <= last.1 => last.1 = last.1.max(released),
+                            _ => shift.push((pressed, released)),
+                        }
+                    }
+                }
+            }
+            key_events.push(Event::new_down(down, key.key));
+            key_events.push(Event::new_up(up, key.key));
+        }
 
+        // Shift events go first, so on ties Shift is pressed before its key
+        // and released before the next keydown (the sort is stable)
+        let mut events = shift
+            .into_iter()
+            .flat_map(|(pressed, released)| {
+                [
+                    Event::new_down(pressed, Key::Shift),
+                    Event::new_up(released, Key::Shift),
+                ]
+            })
+            .collect::<Vec<_>>();
+        events.extend(key_events);
         events.sort();
 
         events
@@ -417,4 +1262,215 @@ impl KeyEvent {
             Self::KeyUp(k) => keybord.key_up(*k),
         }
     }
+
+    pub const fn key_direction(&self) -> (Key, Direction) {
+        match self {
+            Self::KeyDown(k) => (*k, Direction::Down),
+            Self::KeyUp(k) => (*k, Direction::Up),
+        }
+    }
+}
+
+#[test]
+fn test_layout_from_cmu_header() {
+    let header = csv::StringRecord::from(vec![
+        "subject",
+        "sessionIndex",
+        "rep",
+        "H.period",
+        "DD.period.five",
+        "UD.period.five",
+        "H.five",
+        "DD.five.Shift.r",
+        "UD.five.Shift.r",
+        "H.Shift.r",
+        "DD.Shift.r.Return",
+        "UD.Shift.r.Return",
+        "H.Return",
+    ]);
+    let layout = Layout::from_header(&header).unwrap();
+    let keys = layout
+        .keys
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(keys, [".", "5", "Shift+r", "Return"]);
+
+    let record = csv::StringRecord::from(vec![
+        "s002", "1", "2", "0.1", "0.3", "0.2", "0.1", "0.2", "0.1", "0.1", "0.3", "0.2", "0.1",
+    ]);
+    let row = layout.row(0, &record).unwrap();
+    assert_eq!(row.subject, "s002");
+    assert_eq!(row.hold, [0.1, 0.1, 0.1, 0.1]);
+    assert_eq!(row.down_down, [0.3, 0.2, 0.3]);
+    assert_eq!(row.up_down, [0.2, 0.1, 0.2]);
+
+    // both timing models agree on consistent rows
+    let modifier = ModifierOptions {
+        policy: ModifierPolicy::Instant,
+        lead: 0.03,
+        lag: 0.03,
+    };
+    let timestamps = |model| {
+        row.create_events(&layout.keys, model, &modifier)
+            .iter()
+            .map(|event| event.timestamp)
+            .collect::<Vec<_>>()
+    };
+    let dd = timestamps(TimingModel::Dd);
+    let ud = timestamps(TimingModel::Ud);
+    assert!(dd.iter().zip(&ud).all(|(dd, ud)| (dd - ud).abs() < 1e-9));
+
+    // shifted characters are typed with shift
+    assert_eq!(
+        PasswordKey::from_name("R"),
+        PasswordKey::from_name("Shift.r")
+    );
+    assert_eq!(PasswordKey::from_char('!').to_string(), "Shift+1");
+    assert!(Layout::from_header(&csv::StringRecord::from(vec!["subject", "H.a", "H.b"])).is_err());
+}
+
+#[test]
+fn test_modifier_policy() {
+    let header = csv::StringRecord::from(vec![
+        "subject",
+        "sessionIndex",
+        "rep",
+        "H.Shift.r",
+        "DD.Shift.r.a",
+        "UD.Shift.r.a",
+        "H.a",
+        "Lead.Shift.r",
+    ]);
+    let layout = Layout::from_header(&header).unwrap();
+    let record =
+        csv::StringRecord::from(vec!["s002", "1", "1", "0.1", "0.3", "0.2", "0.1", "0.05"]);
+    let row = layout.row(0, &record).unwrap();
+
+    let shift = |policy| {
+        let modifier = ModifierOptions {
+            policy,
+            lead: 0.02,
+            lag: 0.01,
+        };
+        row.create_events(&layout.keys, TimingModel::Ud, &modifier)
+            .into_iter()
+            .filter(|event| event.key_event.key_direction().0 == Key::Shift)
+            // in µs
+            .map(|event| (event.timestamp * 1e6).round())
+            .collect::<Vec<_>>()
+    };
+
+    // the leading Shift starts before the first keydown
+    assert_eq!(shift(ModifierPolicy::Instant), [0.0, 0.0]);
+    assert_eq!(shift(ModifierPolicy::Fixed), [-20_000.0, 110_000.0]);
+    assert_eq!(shift(ModifierPolicy::Dataset), [-50_000.0, 110_000.0]);
+}
+
+#[test]
+fn test_modifier_limits() {
+    let header = csv::StringRecord::from(vec![
+        "subject",
+        "sessionIndex",
+        "rep",
+        "H.Shift.a",
+        "DD.Shift.a.Shift.b",
+        "UD.Shift.a.Shift.b",
+        "H.Shift.b",
+        "DD.Shift.b.c",
+        "UD.Shift.b.c",
+        "H.c",
+    ]);
+    let layout = Layout::from_header(&header).unwrap();
+    let modifier = ModifierOptions {
+        policy: ModifierPolicy::Fixed,
+        lead: 0.03,
+        lag: 0.03,
+    };
+    // (key, direction, µs)
+    let events = |record: Vec<&str>| {
+        let row = layout.row(0, &csv::StringRecord::from(record)).unwrap();
+        row.create_events(&layout.keys, TimingModel::Ud, &modifier)
+            .into_iter()
+            .map(|event| {
+                let (key, direction) = event.key_event.key_direction();
+                (key, direction, (event.timestamp * 1e6).round())
+            })
+            .collect::<Vec<_>>()
+    };
+    let (a, b, c, shift) = (
+        Key::Layout('a'),
+        Key::Layout('b'),
+        Key::Layout('c'),
+        Key::Shift,
+    );
+    let (down, up) = (Direction::Down, Direction::Up);
+
+    // two adjacent capitals share one Shift hold, which ends before the unshifted 'c'
+    // (UD of 10ms is smaller than the lag)
+    assert_eq!(
+        events(vec![
+            "s002", "1", "1", "0.1", "0.15", "0.05", "0.1", "0.11", "0.01", "0.1"
+        ]),
+        [
+            (shift, down, -30_000.0),
+            (a, down, 0.0),
+            (a, up, 100_000.0),
+            (b, down, 150_000.0),
+            (b, up, 250_000.0),
+            (shift, up, 260_000.0),
+            (c, down, 260_000.0),
+            (c, up, 360_000.0),
+        ]
+    );
+
+    // with a negative UD, Shift is released at the next keydown, while 'b' is still held
+    let events = events(vec![
+        "s002", "1", "1", "0.1", "0.15", "0.05", "0.1", "0.08", "-0.02", "0.1",
+    ]);
+    let released = events.iter().position(|e| *e == (shift, up, 230_000.0));
+    let pressed = events.iter().position(|e| *e == (c, down, 230_000.0));
+    assert!(released.unwrap() < pressed.unwrap(), "{events:?}");
+}
+
+#[test]
+fn test_rows_from_recordings() {
+    let header = csv::StringRecord::from(vec![
+        "subject",
+        "sessionIndex",
+        "rep",
+        "H.period",
+        "DD.period.Shift.r",
+        "UD.period.Shift.r",
+        "H.Shift.r",
+        "Lead.Shift.r",
+        "Lag.Shift.r",
+    ]);
+    let layout = Layout::from_header(&header).unwrap();
+    let record = csv::StringRecord::from(vec![
+        "s002", "1", "2", "0.125", "0.25", "0.125", "0.5", "0.25", "0",
+    ]);
+    let row = layout.row(0, &record).unwrap();
+
+    // a CMU row survives the way through key events
+    let codes = [190, 82];
+    let recording = row.recording(&layout.keys, &codes);
+    assert_eq!(recording.name, "s002-1-2");
+    let (keys, rows) = rows_from_recordings(&[recording.clone(), recording]).unwrap();
+    assert_eq!(keys, layout.keys);
+    assert_eq!(rows[1].index, 1);
+    assert_eq!((rows[1].session_index, rows[1].rep), (1, 2));
+    assert_eq!(rows[1].hold, row.hold);
+    assert_eq!(rows[1].down_down, row.down_down);
+    assert_eq!(rows[1].up_down, row.up_down);
+    assert_eq!(rows[1].shift_lead, [None, Some(0.25)]);
+    assert_eq!(rows[1].shift_lag, [None, Some(0.0)]);
+
+    // other passwords and keys that are never released are rejected
+    let mut other = row.recording(&layout.keys, &[190, 83]);
+    assert!(rows_from_recordings(&[row.recording(&layout.keys, &codes), other.clone()]).is_err());
+    other
+        .events
+        .retain(|event| event.direction == Direction::Down);
+    assert!(rows_from_recordings(&[other]).is_err());
 }
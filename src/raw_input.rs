@@ -7,14 +7,21 @@ use std::{
         Arc, Mutex,
     },
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use enigo::{Enigo, KeyboardControllable};
 use rdev::{grab, Event, EventType, Key};
 
-use crate::delay::{delay_busy, delay_sleep};
+use crate::{
+    clock::Clock,
+    delay::{delay_sleep, DelayStrategy},
+    event_log::{Direction, EventLog, EventRecord},
+    realtime::RealtimeOptions,
+    run_info::RunInfo,
+};
 
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
 pub fn capture_raw_input<R: AsRef<Path>>(
     file: R,
     simulate: bool,
@@ -22,9 +29,21 @@ pub fn capture_raw_input<R: AsRef<Path>>(
     delay: f64,
     mut inputs: usize,
     extended: bool,
+    strategy: DelayStrategy,
+    clock: &Arc<dyn Clock>,
+    event_log: Option<&str>,
+    realtime: RealtimeOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("[Browser - OS differnce]");
 
+    // only simulated inputs have an injecting thread the options could apply to
+    if !simulate && realtime.is_requested() {
+        return Err(Box::new(crate::Error(
+            "--cpu, --sched and --mlock apply to the injecting thread, use them with --simulate"
+                .into(),
+        )));
+    }
+
     // output results
     let mut open_file = BufWriter::new(
         File::options()
@@ -39,7 +58,11 @@ pub fn capture_raw_input<R: AsRef<Path>>(
         inputs *= 2;
     }
 
-    let start = Arc::new(Instant::now());
+    // open the per-event log
+    let mut event_log = EventLog::create_optional(event_log)?;
+
+    let start = clock.now();
+    let callback_clock = clock.clone();
 
     // create vec for timing information
     let timings = Arc::new(Mutex::new(Vec::with_capacity(inputs)));
@@ -53,7 +76,7 @@ pub fn capture_raw_input<R: AsRef<Path>>(
     let callback = move |event: Event| -> Option<Event> {
         if let EventType::KeyPress(k) = event.event_type {
             // get time since start
-            let instant_now = start.elapsed();
+            let instant_now = callback_clock.elapsed_since(start);
             let mut lock = timings_clone.lock().unwrap();
 
             // check if expected number of inputs was reached
@@ -71,7 +94,7 @@ pub fn capture_raw_input<R: AsRef<Path>>(
 
         if let EventType::KeyRelease(k) = event.event_type {
             // get time since start
-            let instant_now = start.elapsed();
+            let instant_now = callback_clock.elapsed_since(start);
             let mut lock = timings_clone.lock().unwrap();
 
             // check if expected number of inputs was reached
@@ -92,45 +115,72 @@ pub fn capture_raw_input<R: AsRef<Path>>(
         }
     });
 
-    delay_sleep(1.0);
+    delay_sleep(&**clock, 1.0);
 
     // if inputs should be simulated
     let handle = if simulate {
         println!("Triggering {inputs} inputs after {wait_before_start} sec...");
 
         let stopped_clone = stopped.clone();
+        let clock = clock.clone();
 
         // wait for user to be ready
-        delay_sleep(wait_before_start);
+        delay_sleep(&*clock, wait_before_start);
         println!("Starting...");
 
         // spawn thread to simulate inputs
         Some(thread::spawn(move || {
+            // pin thread and raise its priority
+            let policy = realtime.apply();
+
             let mut enigo = Enigo::new();
             let mut rng = rand::thread_rng();
+            let key = enigo::Key::Layout('0');
+            let mut records = Vec::new();
 
             let mut counter = 0;
 
             // while should not exit
             while counter < inputs {
                 // send "0" key down events
-                enigo.key_down(enigo::Key::Layout('0'));
+                let fired = clock.now();
+                enigo.key_down(key);
+                records.push(EventRecord::unscheduled(
+                    "input",
+                    records.len(),
+                    key,
+                    Direction::Down,
+                    &*clock,
+                    start,
+                    fired,
+                ));
 
                 if extended {
                     // hold key down between 90ms - 200ms
                     let rand_num: f64 = rand::Rng::gen_range(&mut rng, 0.090..0.200);
-                    delay_busy(rand_num);
-
-                    enigo.key_up(enigo::Key::Layout('0'));
+                    strategy.delay(&*clock, rand_num);
+
+                    let fired = clock.now();
+                    enigo.key_up(key);
+                    records.push(EventRecord::unscheduled(
+                        "input",
+                        records.len(),
+                        key,
+                        Direction::Up,
+                        &*clock,
+                        start,
+                        fired,
+                    ));
                     counter += 1;
                 }
 
                 // wait for specified delay (doesn't have to super precise)
-                delay_sleep(delay);
+                delay_sleep(&*clock, delay);
                 counter += 1;
             }
 
             stopped_clone.store(true, Ordering::Relaxed);
+            (records, policy)
         }))
     } else {
         println!("Press '0' to trigger input events! {inputs} inputs needed...");
@@ -143,7 +193,20 @@ pub fn capture_raw_input<R: AsRef<Path>>(
     }
 
     // wait for simulation thread to exit
-    handle.map(std::thread::JoinHandle::join);
+    if let Some(handle) = handle {
+        let (records, policy) = handle.join().expect("simulation thread panicked");
+
+        // record which scheduling was really in effect for the simulation
+        let mut run_info = RunInfo::for_output(file.as_ref());
+        run_info.set("realtime", policy)?;
+        run_info.save()?;
+
+        // write per-event timing data to event log
+        if let Some(log) = &mut event_log {
+            log.write_all(&records)?;
+            log.flush()?;
+        }
+    }
 
     println!("Finished capturing input!");
 
@@ -0,0 +1,191 @@
+use std::fmt::Display;
+
+/// Scheduling policy of the injecting thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedPolicy {
+    /// Default time-sharing scheduling
+    Other,
+    /// Real-time first-in, first-out scheduling
+    Fifo,
+    /// Real-time round-robin scheduling
+    Rr,
+}
+
+/// Command line options for real-time priority and CPU pinning of the injecting thread (Linux only).
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct RealtimeOptions {
+    /// Specifies the CPU core the injecting thread is pinned to
+    #[arg(long)]
+    pub cpu: Option<usize>,
+
+    /// Specifies the scheduling policy of the injecting thread
+    #[arg(long, value_enum, default_value_t = SchedPolicy::Other)]
+    pub sched: SchedPolicy,
+
+    /// Specifies the real-time priority used with 'fifo' and 'rr' scheduling (1 - 99)
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(i32).range(1..=99))]
+    pub priority: i32,
+
+    /// Specifies if all memory of the process should be locked (mlockall) to avoid page faults
+    #[arg(long, default_value_t = false)]
+    pub mlock: bool,
+}
+
+/// Settings that are actually in effect after applying [`RealtimeOptions`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EffectivePolicy {
+    pub cpu: Option<usize>,
+    pub sched: SchedPolicy,
+    pub priority: i32,
+    pub mlock: bool,
+    /// Everything that was requested, but could not be applied
+    pub warnings: Vec<String>,
+}
+
+impl Display for EffectivePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.cpu {
+            Some(cpu) => write!(f, "cpu={cpu}")?,
+            None => write!(f, "cpu=any")?,
+        }
+        write!(
+            f,
+            " sched={:?} priority={} mlock={}",
+            self.sched, self.priority, self.mlock
+        )
+    }
+}
+
+impl RealtimeOptions {
+    /// If any option differs from the default scheduling (a `--priority` alone has no effect).
+    pub fn is_requested(&self) -> bool {
+        self.cpu.is_some() || self.sched != SchedPolicy::Other || self.mlock
+    }
+
+    /// Apply the options to the calling thread.
+    /// Settings that can not be applied (e.g. missing permissions) are skipped with a warning.
+    pub fn apply(&self) -> EffectivePolicy {
+        let mut effective = EffectivePolicy {
+            cpu: None,
+            sched: SchedPolicy::Other,
+            priority: 0,
+            mlock: false,
+            warnings: Vec::new(),
+        };
+
+        imp::apply(self, &mut effective);
+
+        for warning in &effective.warnings {
+            println!("Warning: {warning}");
+        }
+        println!("Effective scheduling: {effective}");
+
+        effective
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::{EffectivePolicy, RealtimeOptions, SchedPolicy};
+
+    pub fn apply(options: &RealtimeOptions, effective: &mut EffectivePolicy) {
+        #[allow(clippy::cast_sign_loss)]
+        let max_cpus = libc::CPU_SETSIZE as usize;
+
+        if let Some(cpu) = options.cpu.filter(|cpu| *cpu >= max_cpus) {
+            effective.warnings.push(format!(
+                "Can not pin thread to cpu {cpu}, max is {max_cpus}"
+            ));
+        } else if let Some(cpu) = options.cpu {
+            // SAFETY: `set` is a valid cpu_set_t and `cpu` is inside its size
+            let res = unsafe {
+                let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+                libc::CPU_ZERO(&mut set);
+                libc::CPU_SET(cpu, &mut set);
+                // pid 0 == calling thread
+                libc::sched_setaffinity(
+                    0,
+                    std::mem::size_of::<libc::cpu_set_t>(),
+                    std::ptr::addr_of!(set),
+                )
+            };
+            if res == 0 {
+                effective.cpu = Some(cpu);
+            } else {
+                effective.warnings.push(format!(
+                    "Failed to pin thread to cpu {cpu}: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+
+        if options.sched != SchedPolicy::Other {
+            let policy = match options.sched {
+                SchedPolicy::Fifo => libc::SCHED_FIFO,
+                SchedPolicy::Rr => libc::SCHED_RR,
+                SchedPolicy::Other => unreachable!(),
+            };
+            let param = libc::sched_param {
+                sched_priority: options.priority,
+            };
+            // SAFETY: `param` is a valid sched_param for the calling thread
+            let res = unsafe {
+                libc::pthread_setschedparam(libc::pthread_self(), policy, std::ptr::addr_of!(param))
+            };
+            if res != 0 {
+                effective.warnings.push(format!(
+                    "Failed to set {:?} scheduling with priority {}: {} (missing CAP_SYS_NICE / rtprio limit?)",
+                    options.sched,
+                    options.priority,
+                    std::io::Error::from_raw_os_error(res)
+                ));
+            }
+        }
+
+        // read back the scheduling that is really in effect
+        let mut policy = 0;
+        let mut param = libc::sched_param { sched_priority: 0 };
+        // SAFETY: both pointers are valid for writes
+        if unsafe {
+            libc::pthread_getschedparam(
+                libc::pthread_self(),
+                std::ptr::addr_of_mut!(policy),
+                std::ptr::addr_of_mut!(param),
+            )
+        } == 0
+        {
+            effective.sched = match policy {
+                libc::SCHED_FIFO => SchedPolicy::Fifo,
+                libc::SCHED_RR => SchedPolicy::Rr,
+                _ => SchedPolicy::Other,
+            };
+            effective.priority = param.sched_priority;
+        }
+
+        if options.mlock {
+            // SAFETY: mlockall has no memory safety preconditions
+            if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } == 0 {
+                effective.mlock = true;
+            } else {
+                effective.warnings.push(format!(
+                    "Failed to lock memory: {} (missing CAP_IPC_LOCK / memlock limit?)",
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::{EffectivePolicy, RealtimeOptions, SchedPolicy};
+
+    pub fn apply(options: &RealtimeOptions, effective: &mut EffectivePolicy) {
+        if options.cpu.is_some() || options.sched != SchedPolicy::Other || options.mlock {
+            effective
+                .warnings
+                .push("Real-time scheduling and cpu pinning are only supported on linux".into());
+        }
+    }
+}
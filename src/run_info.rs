@@ -0,0 +1,87 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Metadata describing a simulation run (options, effective settings, ...).
+/// Stored as JSON next to the output of the run, so results stay reproducible.
+#[derive(Debug, Default)]
+pub struct RunInfo {
+    path: Option<PathBuf>,
+    info: Map<String, Value>,
+}
+
+impl RunInfo {
+    /// Run info stored next to `output` (`<output>.run.json`).
+    pub fn for_output<P: AsRef<Path>>(output: P) -> Self {
+        let mut path = output.as_ref().as_os_str().to_owned();
+        path.push(".run.json");
+        Self::at(path)
+    }
+
+    /// Run info stored next to `output` (`<output>.run.json`), keeping everything already stored there.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the existing run info could not be read.
+    pub fn load_for_output<P: AsRef<Path>>(output: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut run_info = Self::for_output(output);
+        if let Some(path) = run_info.path.as_ref().filter(|path| path.exists()) {
+            let reader = BufReader::new(File::open(path)?);
+            run_info.info = serde_json::from_reader(reader)?;
+        }
+        Ok(run_info)
+    }
+
+    /// Run info stored next to `output`, if an output is given.
+    /// Otherwise the run info is not stored at all.
+    pub fn for_optional_output<P: AsRef<Path>>(output: Option<P>) -> Self {
+        output.map(Self::for_output).unwrap_or_default()
+    }
+
+    /// Run info stored at `path`.
+    pub fn at<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: Some(path.into()),
+            info: Map::new(),
+        }
+    }
+
+    /// Set `key` to `value`, replacing any previous value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if `value` can not be represented as JSON.
+    pub fn set<T: Serialize>(&mut self, key: &str, value: T) -> Result<(), serde_json::Error> {
+        self.info
+            .insert(key.to_owned(), serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    /// Remove the value stored for `key`.
+    pub fn remove(&mut self, key: &str) {
+        self.info.remove(key);
+    }
+
+    /// Value stored for `key`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.info.get(key)
+    }
+
+    /// Write the run info to disk (if it has a path).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if the file could not be written.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            let writer = BufWriter::new(File::create(path)?);
+            serde_json::to_writer_pretty(writer, &self.info)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{abort, clock::Clock, delay::DelayStrategy};
+
+/// Schedules events at fixed offsets from one anchor time.
+///
+/// Waiting for each event against the same anchor (instead of chaining relative delays)
+/// keeps the time spent executing an event from adding up over the following events.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    start: Duration,
+    strategy: DelayStrategy,
+}
+
+impl Scheduler {
+    /// Create a new scheduler, anchored at the current time of `clock`.
+    pub fn start(clock: &Arc<dyn Clock>, strategy: DelayStrategy) -> Self {
+        Self::start_with_lead(clock, strategy, 0.0)
+    }
+
+    /// Create a new scheduler, anchored `lead` seconds after the current time of `clock`.
+    ///
+    /// Events can then be scheduled up to `lead` seconds before the anchor (negative offsets),
+    /// e.g. to compensate for the latency of injecting them.
+    ///
+    /// # Panics
+    /// Panics if passed lead is negativ.
+    pub fn start_with_lead(clock: &Arc<dyn Clock>, strategy: DelayStrategy, lead: f64) -> Self {
+        assert!(lead >= 0.0, "{lead}");
+        Self {
+            clock: clock.clone(),
+            start: clock.now() + Duration::from_secs_f64(lead),
+            strategy,
+        }
+    }
+
+    /// Wait until `offset` seconds after the anchor.
+    /// Returns how many seconds the wait ended after the scheduled time,
+    /// `None` if the run was aborted (the wait ends early).
+    ///
+    /// If the scheduled time already passed, returns immediately.
+    pub fn wait_until(&self, offset: f64) -> Option<f64> {
+        let deadline = if offset < 0.0 {
+            self.start.saturating_sub(Duration::from_secs_f64(-offset))
+        } else {
+            self.start + Duration::from_secs_f64(offset)
+        };
+        let fired = self.strategy.delay_until(&*self.clock, deadline);
+        if abort::requested() {
+            return None;
+        }
+        Some(self.offset_of(fired) - offset)
+    }
+
+    /// Wait until `offset` seconds after the anchor, then execute `event`.
+    /// Returns when the event was scheduled, when it was executed and how long it took,
+    /// `None` if the run was aborted while waiting (`event` is not executed).
+    pub fn fire_at<F: FnOnce()>(&self, offset: f64, event: F) -> Option<Fired> {
+        let lateness = self.wait_until(offset)?;
+        let fired = self.clock.now();
+        event();
+        Some(Fired {
+            scheduled: offset,
+            actual: offset + lateness,
+            took: self.clock.elapsed_since(fired).as_secs_f64(),
+        })
+    }
+
+    /// Seconds elapsed since the anchor.
+    pub fn elapsed(&self) -> f64 {
+        self.clock.elapsed_since(self.start).as_secs_f64()
+    }
+
+    /// Seconds between the anchor and `time` (negative, if `time` is before the anchor).
+    fn offset_of(&self, time: Duration) -> f64 {
+        match time.checked_sub(self.start) {
+            Some(after) => after.as_secs_f64(),
+            None => -self.start.saturating_sub(time).as_secs_f64(),
+        }
+    }
+}
+
+/// Timing of one event executed by a [`Scheduler`].
+/// All values are in seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Fired {
+    /// Offset after the anchor the event was scheduled at
+    pub scheduled: f64,
+    /// Offset after the anchor the event was actually executed at
+    pub actual: f64,
+    /// Time spent executing the event
+    pub took: f64,
+}
+
+impl Fired {
+    /// How many seconds the event was executed after its scheduled time.
+    pub fn lateness(&self) -> f64 {
+        self.actual - self.scheduled
+    }
+}
+
+#[test]
+fn test_execution_time_does_not_accumulate() {
+    use crate::{clock::MockClock, delay::DelayMode};
+
+    let mock = Arc::new(MockClock::new(Duration::from_micros(1)));
+    let clock: Arc<dyn Clock> = mock.clone();
+    let strategy = DelayStrategy {
+        delay_mode: DelayMode::Busy,
+        spin_margin: 0.0,
+    };
+
+    let scheduler = Scheduler::start(&clock, strategy);
+    for i in 0..100 {
+        // every event takes 3ms to execute
+        let fired = scheduler
+            .fire_at(f64::from(i) * 0.010, || {
+                mock.advance(Duration::from_millis(3));
+            })
+            .unwrap();
+
+        assert!(fired.lateness().abs() < 0.000_002, "{fired:?}");
+        assert!((fired.took - 0.003).abs() < 1e-9, "{fired:?}");
+    }
+}
+
+#[test]
+fn test_lead_allows_events_before_anchor() {
+    use crate::{clock::MockClock, delay::DelayMode};
+
+    let mock = Arc::new(MockClock::new(Duration::from_micros(1)));
+    let clock: Arc<dyn Clock> = mock.clone();
+    let strategy = DelayStrategy {
+        delay_mode: DelayMode::Busy,
+        spin_margin: 0.0,
+    };
+
+    let start = clock.now();
+    let scheduler = Scheduler::start_with_lead(&clock, strategy, 0.005);
+
+    // 2ms before the anchor == 3ms after creating the scheduler
+    let fired = scheduler.fire_at(-0.002, || {}).unwrap();
+    assert!(fired.lateness().abs() < 0.000_002, "{fired:?}");
+    let waited = clock.elapsed_since(start).as_secs_f64();
+    assert!((waited - 0.003).abs() < 0.000_002, "{waited}");
+}
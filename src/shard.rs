@@ -0,0 +1,133 @@
+use std::{
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// One of `count` parts a dataset is split into (`index` starts at 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid shard '{s}', expected '<i>/<n>' with 1 <= i <= n");
+
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index = index.trim().parse::<usize>().map_err(|_| invalid())?;
+        let count = count.trim().parse::<usize>().map_err(|_| invalid())?;
+
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// How a dataset is split into shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShardMode {
+    /// Equally sized blocks of rows
+    Contiguous,
+    /// Blocks of roughly equal size, that never split the rows of one subject
+    Subject,
+}
+
+/// Command line options for running only one shard of a dataset.
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct ShardOptions {
+    /// Specifies which shard of the dataset should be simulated, e.g. '2/4' for the second of four shards
+    #[arg(long)]
+    pub shard: Option<Shard>,
+
+    /// Specifies how the dataset is split into shards
+    #[arg(long, value_enum, default_value_t = ShardMode::Contiguous)]
+    pub shard_mode: ShardMode,
+}
+
+impl ShardOptions {
+    /// Rows of the selected shard, given the subject of every row (rows of a subject are expected to be adjacent).
+    /// Returns `None`, if no shard is selected.
+    pub fn range(&self, subjects: &[&str]) -> Option<Range<usize>> {
+        let shard = self.shard?;
+        let total = subjects.len();
+        let target = |k: usize| k * total / shard.count;
+
+        let range = match self.shard_mode {
+            ShardMode::Contiguous => target(shard.index - 1)..target(shard.index),
+            ShardMode::Subject => {
+                // rows where a new subject starts (and the end of the dataset)
+                let boundaries = (0..=total)
+                    .filter(|&i| i == 0 || i == total || subjects[i - 1] != subjects[i])
+                    .collect::<Vec<_>>();
+                // boundary closest to the equally sized split
+                let closest = |k: usize| {
+                    let target = target(k);
+                    boundaries
+                        .iter()
+                        .copied()
+                        .min_by_key(|b| b.abs_diff(target))
+                        .unwrap_or_default()
+                };
+                closest(shard.index - 1)..closest(shard.index)
+            }
+        };
+        Some(range)
+    }
+
+    /// Path of the output for the selected shard, e.g. `data.csv` -> `data.shard-2-of-4.csv`.
+    /// Returns `path` unchanged, if no shard is selected.
+    pub fn output_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        let Some(shard) = self.shard else {
+            return path.to_owned();
+        };
+
+        let mut name = path.file_stem().unwrap_or_default().to_owned();
+        name.push(format!(".shard-{}-of-{}", shard.index, shard.count));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        path.with_file_name(name)
+    }
+}
+
+#[test]
+fn test_shard_ranges() {
+    let options = |shard: &str, shard_mode| ShardOptions {
+        shard: Some(shard.parse().unwrap()),
+        shard_mode,
+    };
+    assert!("0/4".parse::<Shard>().is_err());
+    assert!("5/4".parse::<Shard>().is_err());
+
+    let subjects = ["a", "a", "a", "b", "b", "c", "c", "c", "c", "d"];
+
+    let contiguous = (1..=3)
+        .map(|i| options(&format!("{i}/3"), ShardMode::Contiguous).range(&subjects))
+        .collect::<Vec<_>>();
+    assert_eq!(contiguous, [Some(0..3), Some(3..6), Some(6..10)]);
+
+    let subject = (1..=3)
+        .map(|i| options(&format!("{i}/3"), ShardMode::Subject).range(&subjects))
+        .collect::<Vec<_>>();
+    assert_eq!(subject, [Some(0..3), Some(3..5), Some(5..10)]);
+
+    assert_eq!(
+        options("2/4", ShardMode::Contiguous).output_path("./password_data_rs.csv"),
+        PathBuf::from("./password_data_rs.shard-2-of-4.csv")
+    );
+}
@@ -0,0 +1,88 @@
+use std::fmt::Display;
+
+/// Summary statistics over a list of values.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Summary {
+    /// Calculate summary statistics of `values`.
+    /// Returns `None` if `values` is empty.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let variance = sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+
+        Some(Self {
+            count,
+            min: sorted[0],
+            max: sorted[count - 1],
+            mean,
+            stddev: variance.sqrt(),
+            p50: percentile(&sorted, 0.50),
+            p90: percentile(&sorted, 0.90),
+            p99: percentile(&sorted, 0.99),
+        })
+    }
+}
+
+impl Display for Summary {
+    /// Formats all values as microseconds.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "n={} min={:.1}µs max={:.1}µs mean={:.1}µs stddev={:.1}µs p50={:.1}µs p90={:.1}µs p99={:.1}µs",
+            self.count,
+            self.min * 1e6,
+            self.max * 1e6,
+            self.mean * 1e6,
+            self.stddev * 1e6,
+            self.p50 * 1e6,
+            self.p90 * 1e6,
+            self.p99 * 1e6,
+        )
+    }
+}
+
+/// Nearest-rank percentile of already sorted values.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[test]
+fn test_summary() {
+    let values = (1..=100).map(f64::from).collect::<Vec<_>>();
+    let summary = Summary::new(&values).unwrap();
+
+    assert_eq!(summary.count, 100);
+    assert!((summary.min - 1.0).abs() < f64::EPSILON);
+    assert!((summary.max - 100.0).abs() < f64::EPSILON);
+    assert!((summary.mean - 50.5).abs() < f64::EPSILON);
+    assert!((summary.p50 - 50.0).abs() < f64::EPSILON);
+    assert!((summary.p90 - 90.0).abs() < f64::EPSILON);
+    assert!((summary.p99 - 99.0).abs() < f64::EPSILON);
+
+    assert!(Summary::new(&[]).is_none());
+}
@@ -1,39 +1,252 @@
-use enigo::{Enigo, Key, KeyboardControllable}; //MouseButton, MouseControllable};
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{BufWriter, Write},
+    sync::Arc,
+};
 
-use crate::delay::{delay_busy, delay_sleep};
+use clap::ValueEnum;
+use enigo::{Enigo, Key, KeyboardControllable, MouseButton, MouseControllable};
 
-pub fn browser_timer_sampler(iterations: usize, delay: f64) {
+use crate::{
+    clock::Clock,
+    delay::{delay_sleep, DelayStrategy},
+    distribution::Distribution,
+    event_log::{Direction, EventLog, EventRecord},
+    realtime::RealtimeOptions,
+    run_info::RunInfo,
+    scheduler::{Fired, Scheduler},
+    stats::Summary,
+    Error,
+};
+
+/// Input event simulated by the timer sampler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputKind {
+    /// Left mouse click (down and up without a hold)
+    MouseClick,
+    /// Left mouse button down, held, then up
+    MouseDownUp,
+    /// Key down only (key is never released)
+    KeyDown,
+    /// Key down, held, then up
+    KeyDownUp,
+}
+
+impl InputKind {
+    const fn is_mouse(self) -> bool {
+        matches!(self, Self::MouseClick | Self::MouseDownUp)
+    }
+
+    const fn has_release(self) -> bool {
+        matches!(self, Self::MouseDownUp | Self::KeyDownUp)
+    }
+}
+
+/// Command line options for sweeping the phase of events across a window (e.g. a browser's clamping grid).
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct PhaseSweep {
+    /// Specifies the window (in seconds) event phases are swept across, e.g. '0.001' (enables the phase sweep,
+    /// replacing 'iterations' and 'seed', the delay has to be a constant)
+    #[arg(long = "phase-window", conflicts_with_all = ["iterations", "seed"])]
+    pub window: Option<f64>,
+
+    /// Specifies how many seconds each phase is shifted from the previous one
+    #[arg(long = "phase-step", default_value_t = 0.000_01)]
+    pub step: f64,
+
+    /// Specifies how often each phase is repeated
+    #[arg(long = "phase-repeats", default_value_t = 10)]
+    pub repeats: usize,
+}
+
+/// Mouse button used by the mouse input kinds.
+const MOUSE_BUTTON: MouseButton = MouseButton::Left;
+
+/// Key or mouse button an input event is sent for.
+#[derive(Clone, Copy)]
+enum Input {
+    Key(Key),
+    Mouse(MouseButton),
+}
+
+impl Debug for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Key(key) => key.fmt(f),
+            Self::Mouse(button) => button.fmt(f),
+        }
+    }
+}
+
+impl Input {
+    /// Input sent as `i`-th event of `kind`.
+    const fn nth(kind: InputKind, i: usize) -> Self {
+        if kind.is_mouse() {
+            return Self::Mouse(MOUSE_BUTTON);
+        }
+
+        #[cfg(target_os = "macos")]
+        let key = Key::Layout('a');
+
+        #[cfg(not(target_os = "macos"))]
+        let key = KEYS[i % KEYS.len()];
+
+        Self::Key(key)
+    }
+
+    /// Press the key/button (or click it, if `click` is set).
+    fn press(self, enigo: &mut Enigo, click: bool) {
+        match self {
+            Self::Key(key) => enigo.key_down(key),
+            Self::Mouse(button) if click => enigo.mouse_click(button),
+            Self::Mouse(button) => enigo.mouse_down(button),
+        }
+    }
+
+    /// Release the key/button.
+    fn release(self, enigo: &mut Enigo) {
+        match self {
+            Self::Key(key) => enigo.key_up(key),
+            Self::Mouse(button) => enigo.mouse_up(button),
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss, clippy::too_many_arguments)]
+pub fn browser_timer_sampler(
+    iterations: usize,
+    delay: &Distribution,
+    seed: Option<u64>,
+    kind: InputKind,
+    hold: f64,
+    output: Option<&str>,
+    strategy: DelayStrategy,
+    clock: &Arc<dyn Clock>,
+    event_log: Option<&str>,
+    realtime: RealtimeOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("[Browser timestamp samples]");
 
+    // pin thread and raise its priority, record what is really in effect
+    let mut run_info = RunInfo::for_optional_output(output.or(event_log));
+    run_info.set("realtime", realtime.apply())?;
+    // draw all delays up front, so drawing them does not add to the measured delays
+    let seed = seed.unwrap_or_else(rand::random);
+    let requested = delay.sampler(iterations, seed).collect::<Vec<_>>();
+    println!("Delays: {delay} (seed {seed})");
+    run_info.set("delay", delay.to_string())?;
+    run_info.set("seed", seed)?;
+    run_info.set("kind", kind)?;
+    run_info.set("hold", kind.has_release().then_some(hold))?;
+    run_info.save()?;
+
+    // open the output file
+    let mut output = output
+        .map(|output| File::create(output).map(BufWriter::new))
+        .transpose()?;
+
+    // open the per-event log
+    let mut event_log = EventLog::create_optional(event_log)?;
+
     // create new input
     let mut enigo = Enigo::new();
 
     // wait for user to be ready
-    delay_sleep(10.0);
+    delay_sleep(&**clock, 10.0);
 
     println!("Starting...");
 
-    // pre-allocate vec, storing how long was really waited
+    // pre-allocate vec, storing which input was sent and how long was really waited
     let mut delays = Vec::with_capacity(iterations);
+    let mut records = Vec::new();
+    let start = clock.now();
+    // offset after start each event should have been sent at
+    let mut scheduled = 0.0;
 
-    for i in 0..iterations {
-        // wait specified delay before next mouse click
-        delays.push(delay_busy(delay));
-
-        // simulate mouse click
-        // enigo.mouse_click(MouseButton::Left);
+    for (i, &delay) in requested.iter().enumerate() {
+        // wait requested delay before next input
+        let actual = strategy.delay(&**clock, delay);
+        scheduled += delay;
 
         // simulate input
-        #[cfg(target_os = "macos")]
-        enigo.key_down(Key::Layout('a'));
+        let input = Input::nth(kind, i);
+        let fired = clock.now();
+        input.press(&mut enigo, kind == InputKind::MouseClick);
 
-        #[cfg(not(target_os = "macos"))]
-        enigo.key_down(KEYS[i % KEYS.len()]);
+        if event_log.is_some() {
+            let fired = Fired {
+                scheduled,
+                actual: fired.saturating_sub(start).as_secs_f64(),
+                took: clock.elapsed_since(fired).as_secs_f64(),
+            };
+            records.push(EventRecord::from_fired(
+                "timer",
+                i,
+                input,
+                Direction::Down,
+                &fired,
+            ));
+        }
+
+        // hold input, then release it
+        if kind.has_release() {
+            strategy.delay(&**clock, hold);
+            scheduled += hold;
+
+            let fired = clock.now();
+            input.release(&mut enigo);
+
+            if event_log.is_some() {
+                let fired = Fired {
+                    scheduled,
+                    actual: fired.saturating_sub(start).as_secs_f64(),
+                    took: clock.elapsed_since(fired).as_secs_f64(),
+                };
+                records.push(EventRecord::from_fired(
+                    "timer",
+                    i,
+                    input,
+                    Direction::Up,
+                    &fired,
+                ));
+            }
+        }
+
+        delays.push((input, delay, actual.as_secs_f64()));
+    }
+
+    if let Some(log) = &mut event_log {
+        log.write_all(&records)?;
+        log.flush()?;
     }
 
     // output delays
-    // TODO: write to file?
-    println!("{delays:?}");
+    if let Some(output) = &mut output {
+        writeln!(output, "i,kind,input,requested,actual")?;
+        let kind = kind.to_possible_value().expect("no skipped variants");
+        for (i, (input, requested, actual)) in delays.iter().enumerate() {
+            writeln!(
+                output,
+                "{i},{},{input:?},{requested},{actual}",
+                kind.get_name()
+            )?;
+        }
+        output.flush()?;
+    }
+
+    // how much longer than requested each delay took
+    let overshoot = delays
+        .iter()
+        .map(|(_, requested, actual)| actual - requested)
+        .collect::<Vec<_>>();
+    if let Some(summary) = Summary::new(&overshoot) {
+        println!("Delay overshoot: {summary}");
+        run_info.set("overshoot", summary)?;
+        run_info.save()?;
+    }
+    Ok(())
 }
 
 const KEYS: [Key; 28] = [
@@ -66,3 +279,165 @@ const KEYS: [Key; 28] = [
     Key::Delete,
     Key::Layout('.'),
 ];
+
+/// Sends events at fixed phase offsets inside `window`, every phase is repeated `sweep.repeats` times.
+///
+/// Events are scheduled against one anchor, at `n * period + phase`.
+/// The period is the (constant) delay, rounded to a multiple of the window,
+/// so the phase of each event inside the window stays what it was intended to be.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss,
+    clippy::too_many_arguments,
+    clippy::too_many_lines
+)]
+pub fn phase_sweep_sampler(
+    window: f64,
+    sweep: PhaseSweep,
+    delay: &Distribution,
+    kind: InputKind,
+    hold: f64,
+    output: Option<&str>,
+    strategy: DelayStrategy,
+    clock: &Arc<dyn Clock>,
+    event_log: Option<&str>,
+    realtime: RealtimeOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("[Browser timestamp phase sweep]");
+
+    let Distribution::Constant(delay) = *delay else {
+        return Err(Box::new(Error(format!(
+            "Phase sweep needs a constant delay, got: {delay}"
+        ))));
+    };
+    if window <= 0.0 || sweep.step <= 0.0 || sweep.step > window {
+        return Err(Box::new(Error(format!(
+            "Phase step ({}) must be greater than 0 and not greater than the window ({window})",
+            sweep.step
+        ))));
+    }
+
+    let phases = (window / sweep.step).round() as usize;
+    let period = (delay / window).round().max(1.0) * window;
+    if kind.has_release() && hold + window >= period {
+        return Err(Box::new(Error(format!(
+            "Hold ({hold}) plus phase window ({window}) must be shorter than the period ({period})"
+        ))));
+    }
+    println!(
+        "Sweeping {phases} phases of {}µs across {}µs, {} repeats, period {period}s",
+        sweep.step * 1e6,
+        window * 1e6,
+        sweep.repeats
+    );
+
+    // pin thread and raise its priority, record what is really in effect
+    let mut run_info = RunInfo::for_optional_output(output.or(event_log));
+    run_info.set("realtime", realtime.apply())?;
+    run_info.set("phase_window", window)?;
+    run_info.set("phase_step", sweep.step)?;
+    run_info.set("phase_repeats", sweep.repeats)?;
+    run_info.set("period", period)?;
+    run_info.set("kind", kind)?;
+    run_info.set("hold", kind.has_release().then_some(hold))?;
+    run_info.save()?;
+
+    // open the output file
+    let mut output = output
+        .map(|output| File::create(output).map(BufWriter::new))
+        .transpose()?;
+
+    // open the per-event log
+    let mut event_log = EventLog::create_optional(event_log)?;
+
+    // create new input
+    let mut enigo = Enigo::new();
+
+    // wait for user to be ready
+    delay_sleep(&**clock, 10.0);
+
+    println!("Starting...");
+
+    let mut events = Vec::with_capacity(phases * sweep.repeats);
+    let mut records = Vec::new();
+    let scheduler = Scheduler::start(clock, strategy);
+
+    // cycle through all phases for each repeat, so slow drift does not correlate with the phase
+    'sweep: for repeat in 0..sweep.repeats {
+        for step in 0..phases {
+            let i = repeat * phases + step;
+            let phase = step as f64 * sweep.step;
+            let offset = (i + 1) as f64 * period + phase;
+
+            let input = Input::nth(kind, i);
+            let Some(down) = scheduler.fire_at(offset, || {
+                input.press(&mut enigo, kind == InputKind::MouseClick);
+            }) else {
+                break 'sweep;
+            };
+
+            if event_log.is_some() {
+                records.push(EventRecord::from_fired(
+                    "phase-sweep",
+                    i,
+                    input,
+                    Direction::Down,
+                    &down,
+                ));
+            }
+
+            // hold input, then release it
+            if kind.has_release() {
+                let Some(up) = scheduler.fire_at(offset + hold, || input.release(&mut enigo))
+                else {
+                    break 'sweep;
+                };
+
+                if event_log.is_some() {
+                    records.push(EventRecord::from_fired(
+                        "phase-sweep",
+                        i,
+                        input,
+                        Direction::Up,
+                        &up,
+                    ));
+                }
+            }
+
+            events.push((input, repeat, phase, down));
+        }
+    }
+
+    if let Some(log) = &mut event_log {
+        log.write_all(&records)?;
+        log.flush()?;
+    }
+
+    // output intended phase and timing of each event
+    if let Some(output) = &mut output {
+        writeln!(output, "i,kind,input,repeat,phase,scheduled,actual")?;
+        let kind = kind.to_possible_value().expect("no skipped variants");
+        for (i, (input, repeat, phase, fired)) in events.iter().enumerate() {
+            writeln!(
+                output,
+                "{i},{},{input:?},{repeat},{phase},{},{}",
+                kind.get_name(),
+                fired.scheduled,
+                fired.actual
+            )?;
+        }
+        output.flush()?;
+    }
+
+    let lateness = events
+        .iter()
+        .map(|(_, _, _, fired)| fired.lateness())
+        .collect::<Vec<_>>();
+    if let Some(summary) = Summary::new(&lateness) {
+        println!("Event lateness: {summary}");
+        run_info.set("lateness", summary)?;
+        run_info.save()?;
+    }
+    Ok(())
+}